@@ -0,0 +1,21 @@
+-- Existing passwords count as changed now, so enabling the expiry doesn't
+-- force every user to change their password at once.
+ALTER TABLE users
+    ADD COLUMN password_changed_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ADD COLUMN password_change_required BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN password_expiry_exempt BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE password_history (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    password VARCHAR(128) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX ix_password_history_user_id ON password_history(user_id);
+
+INSERT INTO password_history (user_id, password, created_at)
+SELECT id, password, created_at FROM users;
+
+-- Signed in sessions are bound to the version instead of the password hash,
+-- so upgrading the hash on sign in doesn't sign the user out elsewhere.
+ALTER TABLE users ADD COLUMN session_version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,14 @@
+-- Set when the user deletes their account, the row is purged afterwards
+ALTER TABLE users ADD COLUMN scheduled_deletion_at TIMESTAMPTZ;
+CREATE INDEX ix_users_scheduled_deletion_at ON users(scheduled_deletion_at)
+    WHERE scheduled_deletion_at IS NOT NULL;
+
+-- Maps the signed in sessions of the session store to their users. There is
+-- no foreign key, as the sessions of purged users are deleted through it
+-- after the users themselves.
+CREATE TABLE user_sessions (
+    user_id INTEGER NOT NULL,
+    session_id TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, session_id)
+);
@@ -1,8 +1,18 @@
+const TOAST_TIMEOUT_MS = 5000;
+
+function dismissToasts() {
+  document.querySelectorAll('#toasts > *').forEach(function(toast) {
+    setTimeout(function() { toast.remove(); }, TOAST_TIMEOUT_MS);
+  });
+}
+
 document.addEventListener("DOMContentLoaded", (event) => {
   document.body.addEventListener('htmx:beforeSwap', function(evt) {
-    if ([401, 409, 422].includes(evt.detail.xhr.status)) {
+    if ([401, 403, 409, 422, 429, 500].includes(evt.detail.xhr.status)) {
       evt.detail.shouldSwap = true;
       evt.detail.isError = false;
     }
   });
+  document.body.addEventListener('htmx:oobAfterSwap', dismissToasts);
+  dismissToasts();
 })
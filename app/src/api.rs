@@ -1,6 +1,10 @@
+pub mod admin;
 pub mod app;
 pub mod constant;
+pub mod error;
+pub mod htmx;
 pub mod layer;
+pub mod metrics;
 pub mod middleware;
 pub mod response;
 pub mod router;
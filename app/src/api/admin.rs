@@ -0,0 +1,110 @@
+use crate::{
+    api::error::AppError,
+    db::user::{set_password_change_required, set_password_expiry_exempt},
+    libs::admin_token::has_admin_token,
+    state::AdminState,
+    tracing::LogFilterError,
+};
+use axum::{
+    extract::{Path, Request, State},
+    http::StatusCode,
+    middleware::{from_fn_with_state, Next},
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Router,
+};
+
+pub fn create_admin_router(state: AdminState) -> Router {
+    Router::new()
+        .route(
+            "/users/:id/password-change-required",
+            put(set_user_password_change_required),
+        )
+        .route(
+            "/users/:id/password-expiry-exempt",
+            put(set_user_password_expiry_exempt),
+        )
+        .route_layer(from_fn_with_state(state.clone(), require_admin_token))
+        .route("/log-filter", get(get_log_filter).put(set_log_filter))
+        .with_state(state)
+}
+
+// The user endpoints change security state, so being able to reach the admin
+// address isn't enough. They are disabled when no token is configured.
+async fn require_admin_token(
+    State(state): State<AdminState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let is_authorized = state
+        .token
+        .as_deref()
+        .map_or(false, |token| has_admin_token(request.headers(), token));
+    if !is_authorized {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+async fn get_log_filter(State(state): State<AdminState>) -> Response {
+    match state.log_filter.get() {
+        Ok(directives) => directives.into_response(),
+        Err(e) => AppError::internal("Failed to read the log filter", e).into_response(),
+    }
+}
+
+// Accepts the same directives as the LOGGING_FILTER env variable, e.g.
+// `curl -X PUT --data 'info,app=trace' <admin address>/log-filter`
+async fn set_log_filter(State(state): State<AdminState>, directives: String) -> Response {
+    let directives = directives.trim();
+    match state.log_filter.set(directives) {
+        Ok(_) => directives.to_owned().into_response(),
+        Err(LogFilterError::ParseError(e)) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()).into_response()
+        }
+        Err(e) => AppError::internal("Failed to change the log filter", e).into_response(),
+    }
+}
+
+fn parse_flag(body: &str) -> Result<bool, Response> {
+    body.trim()
+        .parse()
+        .map_err(|_| (StatusCode::UNPROCESSABLE_ENTITY, "Expected true or false").into_response())
+}
+
+// Takes effect on the next sign-in, e.g. `curl -X PUT --data 'true'
+// -H 'Authorization: Bearer <ADMIN_TOKEN>' <admin address>/users/1/password-change-required`
+async fn set_user_password_change_required(
+    State(state): State<AdminState>,
+    Path(id): Path<i32>,
+    body: String,
+) -> Response {
+    let required = match parse_flag(&body) {
+        Ok(required) => required,
+        Err(response) => return response,
+    };
+    match set_password_change_required(id, required, &state.db).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => AppError::internal("Failed to require password change", e).into_response(),
+    }
+}
+
+// Exempt users (e.g. service accounts) are not affected by PASSWORD_MAX_AGE_DAYS
+async fn set_user_password_expiry_exempt(
+    State(state): State<AdminState>,
+    Path(id): Path<i32>,
+    body: String,
+) -> Response {
+    let exempt = match parse_flag(&body) {
+        Ok(exempt) => exempt,
+        Err(response) => return response,
+    };
+    match set_password_expiry_exempt(id, exempt, &state.db).await {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            AppError::internal("Failed to change password expiry exemption", e).into_response()
+        }
+    }
+}
@@ -1,4 +1,6 @@
+pub mod account;
 pub mod asset;
 pub mod auth;
+pub mod health;
 pub mod main;
 pub mod protected;
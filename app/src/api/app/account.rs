@@ -0,0 +1,474 @@
+use crate::{
+    api::{
+        app::auth::{
+            validate_confirm_password, validate_new_password, validate_unbreached_password,
+        },
+        constant::{
+            ACCOUNT_DELETION_SCHEDULED_MESSAGE, CHANGE_PASSWORD_ROUTE, DATA_EXPORT_DOWNLOAD_ROUTE,
+            DATA_EXPORT_REQUESTED_EVENT, DATA_EXPORT_ROUTE, DATA_EXPORT_STATUS_ROUTE,
+            DELETE_ACCOUNT_ROUTE, HOME_ROUTE, PAGE_CONTENT_SELECTOR, PASSWORD_CHANGED_MESSAGE,
+            PASSWORD_HASHING_RETRY_AFTER, PASSWORD_REUSED_MESSAGE, PROTECTED_ROUTE,
+            REAUTHENTICATE_ROUTE, SIGNIN_ROUTE,
+        },
+        error::AppError,
+        htmx::HtmxResponse,
+        middleware::{set_default_response_headers_for_protected, RenderOptions},
+        response::create_redirect_after_submission,
+    },
+    controllers::{
+        account::{delete_account, request_data_export, DATA_EXPORT_FILE_NAME},
+        auth::{change_password, ChangePasswordError},
+    },
+    db::{
+        data_export::{get_data_export, get_data_export_data, DataExport, DataExportStatus},
+        user::get_user_email,
+    },
+    libs::{
+        archive::zip_file,
+        auth::{AuthSession, Backend},
+        flash::Flash,
+        password_rotation::{
+            enforce_password_change, is_password_change_pending, PasswordChangeRedirect,
+        },
+        recent_auth::{require_recent_auth, RecentAuthPolicy},
+        secret_key::SecretKeys,
+        validation::is_local_path,
+    },
+    state::AppState,
+};
+use askama_axum::Template;
+use axum::{
+    extract::{Extension, Query, State},
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+        StatusCode,
+    },
+    middleware::{from_fn_with_state, map_response},
+    response::{IntoResponse, Response},
+    routing::get,
+    Form, Router,
+};
+use axum_login::login_required;
+use serde::Deserialize;
+use time::{Duration, OffsetDateTime};
+use tracing::instrument;
+
+const RECENT_AUTH_POLICY: RecentAuthPolicy = RecentAuthPolicy {
+    max_age: Duration::minutes(10),
+    reauthenticate_route: REAUTHENTICATE_ROUTE,
+    target: PAGE_CONTENT_SELECTOR,
+};
+
+pub const PASSWORD_CHANGE_REDIRECT: PasswordChangeRedirect = PasswordChangeRedirect {
+    route: CHANGE_PASSWORD_ROUTE,
+    target: PAGE_CONTENT_SELECTOR,
+};
+
+pub fn create_account_router() -> Router<AppState> {
+    Router::new()
+        .route(
+            DELETE_ACCOUNT_ROUTE,
+            get(get_delete_account).post(post_delete_account),
+        )
+        .route(
+            DATA_EXPORT_ROUTE,
+            get(get_data_export_page).post(post_data_export),
+        )
+        .layer(from_fn_with_state(RECENT_AUTH_POLICY, require_recent_auth))
+        // Polled while an export is pending, so it must not ask to reauthenticate
+        .route(DATA_EXPORT_STATUS_ROUTE, get(get_data_export_status))
+        .layer(from_fn_with_state(
+            PASSWORD_CHANGE_REDIRECT,
+            enforce_password_change,
+        ))
+        .route(
+            CHANGE_PASSWORD_ROUTE,
+            get(get_change_password)
+                .post(post_change_password)
+                .layer(from_fn_with_state(RECENT_AUTH_POLICY, require_recent_auth)),
+        )
+        .layer(login_required!(Backend, login_url = SIGNIN_ROUTE))
+        // The signed link is the only credential needed to download an export
+        .route(DATA_EXPORT_DOWNLOAD_ROUTE, get(download_data_export))
+        .layer(map_response(set_default_response_headers_for_protected))
+}
+
+#[derive(Deserialize)]
+struct ChangePasswordParams {
+    next: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/change-password/index.html")]
+struct ChangePasswordTemplate<'a> {
+    options: RenderOptions,
+    // The password expired or an admin required a change
+    required: bool,
+    form_data: ChangePasswordFormData<'a>,
+}
+
+#[derive(Default)]
+struct ChangePasswordFormData<'a> {
+    focus: ChangePasswordFormField,
+    next: Option<&'a str>,
+    errors: ChangePasswordFormErrors<'a>,
+}
+
+#[derive(PartialEq, Default)]
+enum ChangePasswordFormField {
+    #[default]
+    Password,
+    ConfirmPassword,
+}
+
+#[derive(Default)]
+struct ChangePasswordFormErrors<'a> {
+    password: Option<&'a str>,
+    confirm_password: Option<&'a str>,
+}
+
+#[instrument(skip_all)]
+async fn get_change_password(
+    Extension(options): Extension<RenderOptions>,
+    auth_session: AuthSession,
+    params: Query<ChangePasswordParams>,
+) -> impl IntoResponse {
+    let required = match is_password_change_pending(&auth_session.session).await {
+        Ok(required) => required,
+        Err(e) => {
+            return AppError::internal("Failed to read pending password change", e).into_response()
+        }
+    };
+    ChangePasswordTemplate {
+        options,
+        required,
+        form_data: ChangePasswordFormData {
+            next: params.next.as_deref(),
+            ..Default::default()
+        },
+    }
+    .into_response()
+}
+
+#[derive(Deserialize)]
+struct ChangePasswordPayload {
+    password: String,
+    confirm_password: String,
+    next: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/change-password/form.html")]
+struct ChangePasswordFormTemplate<'a> {
+    form_data: ChangePasswordFormData<'a>,
+}
+
+async fn render_change_password_form(
+    options: RenderOptions,
+    auth_session: &AuthSession,
+    status_code: StatusCode,
+    form_data: ChangePasswordFormData<'_>,
+) -> Response {
+    if options.use_base_layout {
+        let required = is_password_change_pending(&auth_session.session)
+            .await
+            .unwrap_or_default();
+        let template = ChangePasswordTemplate {
+            options,
+            required,
+            form_data,
+        };
+        return (status_code, template).into_response();
+    }
+    let template = ChangePasswordFormTemplate { form_data };
+    (status_code, template).into_response()
+}
+
+#[instrument(skip_all)]
+async fn post_change_password(
+    State(state): State<AppState>,
+    Extension(options): Extension<RenderOptions>,
+    mut auth_session: AuthSession,
+    flash: Flash,
+    Form(payload): Form<ChangePasswordPayload>,
+) -> impl IntoResponse {
+    let Some(user_id) = auth_session.user.as_ref().map(|user| user.id) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let email = match get_user_email(user_id, &state.db).await {
+        Ok(email) => email.unwrap_or_default(),
+        Err(e) => return AppError::internal("Failed to change password", e).into_response(),
+    };
+
+    let mut form_data = ChangePasswordFormData {
+        next: payload.next.as_deref(),
+        ..Default::default()
+    };
+    form_data.errors.confirm_password =
+        validate_confirm_password(&payload.password, &payload.confirm_password);
+    if form_data.errors.confirm_password.is_some() {
+        form_data.focus = ChangePasswordFormField::ConfirmPassword;
+    }
+    form_data.errors.password =
+        match validate_new_password(&payload.password, &email, &state.password_policy) {
+            Some(error) => Some(error),
+            None if form_data.errors.confirm_password.is_none() => {
+                validate_unbreached_password(&state, &payload.password).await
+            }
+            None => None,
+        };
+    if form_data.errors.password.is_some() {
+        form_data.focus = ChangePasswordFormField::Password;
+    }
+    if form_data.errors.password.is_some() || form_data.errors.confirm_password.is_some() {
+        return render_change_password_form(
+            options,
+            &auth_session,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            form_data,
+        )
+        .await;
+    }
+
+    match change_password(
+        payload.password,
+        &state.db,
+        &state.password_hashing,
+        &state.password_rotation,
+        &mut auth_session,
+    )
+    .await
+    {
+        Err(ChangePasswordError::PasswordReusedError) => {
+            form_data.errors.password = Some(PASSWORD_REUSED_MESSAGE);
+            render_change_password_form(
+                options,
+                &auth_session,
+                StatusCode::UNPROCESSABLE_ENTITY,
+                form_data,
+            )
+            .await
+        }
+        Err(e) if e.is_hashing_busy() => {
+            AppError::service_unavailable("Failed to change password", PASSWORD_HASHING_RETRY_AFTER)
+                .into_response()
+        }
+        Err(e) => AppError::internal("Failed to change password", e).into_response(),
+        Ok(_) => {
+            flash.success(PASSWORD_CHANGED_MESSAGE).await;
+            let next_url = payload
+                .next
+                .as_deref()
+                .filter(|next| is_local_path(next))
+                .unwrap_or(PROTECTED_ROUTE);
+            create_redirect_after_submission(&options, StatusCode::OK, next_url)
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "pages/delete-account/index.html")]
+struct DeleteAccountTemplate {
+    options: RenderOptions,
+    grace_period_days: u16,
+}
+
+#[instrument(skip_all)]
+async fn get_delete_account(
+    State(state): State<AppState>,
+    Extension(options): Extension<RenderOptions>,
+) -> DeleteAccountTemplate {
+    DeleteAccountTemplate {
+        options,
+        grace_period_days: state.account_deletion_grace_period_days,
+    }
+}
+
+#[instrument(skip_all)]
+async fn post_delete_account(
+    State(state): State<AppState>,
+    Extension(options): Extension<RenderOptions>,
+    mut auth_session: AuthSession,
+    flash: Flash,
+) -> impl IntoResponse {
+    match delete_account(
+        state.account_deletion_grace_period_days,
+        &state.db,
+        &mut auth_session,
+    )
+    .await
+    {
+        Err(e) => AppError::internal("Failed to delete account", e).into_response(),
+        Ok(_) => {
+            flash.info(ACCOUNT_DELETION_SCHEDULED_MESSAGE).await;
+            create_redirect_after_submission(&options, StatusCode::OK, HOME_ROUTE)
+        }
+    }
+}
+
+#[derive(Default)]
+struct DataExportState {
+    pending: bool,
+    failed: bool,
+    download_url: Option<String>,
+    expires_in_hours: i64,
+}
+
+const DATA_EXPORT_SIGNATURE_PURPOSE: &str = "data-export";
+
+fn create_data_export_signature_message(id: i32, expires_at: i64) -> String {
+    format!("{}:{}", id, expires_at)
+}
+
+fn create_data_export_state(
+    export: Option<DataExport>,
+    secret_keys: &SecretKeys,
+) -> DataExportState {
+    let Some(export) = export else {
+        return DataExportState::default();
+    };
+    let download_url = (export.status == DataExportStatus::Ready).then(|| {
+        let signature = secret_keys.sign(
+            DATA_EXPORT_SIGNATURE_PURPOSE,
+            &create_data_export_signature_message(export.id, export.expires_at),
+        );
+        format!(
+            "{}?id={}&expires={}&signature={}",
+            DATA_EXPORT_DOWNLOAD_ROUTE, export.id, export.expires_at, signature
+        )
+    });
+    let expires_in_seconds = export.expires_at - OffsetDateTime::now_utc().unix_timestamp();
+    DataExportState {
+        pending: export.status == DataExportStatus::Pending,
+        failed: export.status == DataExportStatus::Failed,
+        download_url,
+        expires_in_hours: (expires_in_seconds + 3599).div_euclid(3600).max(1),
+    }
+}
+
+async fn get_current_data_export_state(
+    state: &AppState,
+    auth_session: &AuthSession,
+) -> Result<DataExportState, Response> {
+    let Some(user_id) = auth_session.user.as_ref().map(|user| user.id) else {
+        return Err(StatusCode::UNAUTHORIZED.into_response());
+    };
+    match get_data_export(user_id, &state.db).await {
+        Ok(export) => Ok(create_data_export_state(export, &state.secret_keys)),
+        Err(e) => Err(AppError::internal("Failed to get data export", e).into_response()),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "pages/data-export/index.html")]
+struct DataExportTemplate {
+    options: RenderOptions,
+    export: DataExportState,
+}
+
+#[instrument(skip_all)]
+async fn get_data_export_page(
+    State(state): State<AppState>,
+    Extension(options): Extension<RenderOptions>,
+    auth_session: AuthSession,
+) -> impl IntoResponse {
+    match get_current_data_export_state(&state, &auth_session).await {
+        Ok(export) => DataExportTemplate { options, export }.into_response(),
+        Err(response) => response,
+    }
+}
+
+#[instrument(skip_all)]
+async fn post_data_export(
+    State(state): State<AppState>,
+    Extension(options): Extension<RenderOptions>,
+    auth_session: AuthSession,
+) -> impl IntoResponse {
+    let Some(user_id) = auth_session.user.as_ref().map(|user| user.id) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    match request_data_export(user_id, state.data_export_expiry_hours, &state.db).await {
+        Err(e) => AppError::internal("Failed to request data export", e).into_response(),
+        Ok(_) if options.use_base_layout => {
+            create_redirect_after_submission(&options, StatusCode::ACCEPTED, DATA_EXPORT_ROUTE)
+        }
+        // The status on the page reloads itself on the event
+        Ok(_) => (
+            StatusCode::ACCEPTED,
+            HtmxResponse::new().trigger(DATA_EXPORT_REQUESTED_EVENT),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "pages/data-export/status.html")]
+struct DataExportStatusTemplate {
+    export: DataExportState,
+}
+
+#[instrument(skip_all)]
+async fn get_data_export_status(
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+) -> impl IntoResponse {
+    match get_current_data_export_state(&state, &auth_session).await {
+        Ok(export) => DataExportStatusTemplate { export }.into_response(),
+        Err(response) => response,
+    }
+}
+
+#[derive(Deserialize)]
+struct DownloadDataExportParams {
+    id: i32,
+    expires: i64,
+    signature: String,
+    format: Option<String>,
+}
+
+#[instrument(skip_all)]
+async fn download_data_export(
+    State(state): State<AppState>,
+    Query(params): Query<DownloadDataExportParams>,
+) -> impl IntoResponse {
+    let message = create_data_export_signature_message(params.id, params.expires);
+    if !state
+        .secret_keys
+        .verify(DATA_EXPORT_SIGNATURE_PURPOSE, &message, &params.signature)
+        || params.expires <= OffsetDateTime::now_utc().unix_timestamp()
+    {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let data = match get_data_export_data(params.id, &state.db).await {
+        Ok(Some(data)) => data,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => return AppError::internal("Failed to download data export", e).into_response(),
+    };
+    match params.format.as_deref() {
+        Some("zip") => match zip_file(DATA_EXPORT_FILE_NAME, data.as_bytes()) {
+            Ok(archive) => (
+                [
+                    (CONTENT_TYPE, "application/zip"),
+                    (
+                        CONTENT_DISPOSITION,
+                        "attachment; filename=\"data-export.zip\"",
+                    ),
+                ],
+                archive,
+            )
+                .into_response(),
+            Err(e) => AppError::internal("Failed to archive data export", e).into_response(),
+        },
+        _ => (
+            [
+                (CONTENT_TYPE, "application/json".to_owned()),
+                (
+                    CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}\"", DATA_EXPORT_FILE_NAME),
+                ),
+            ],
+            data,
+        )
+            .into_response(),
+    }
+}
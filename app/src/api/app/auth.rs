@@ -1,34 +1,53 @@
 use crate::{
     api::{
         constant::{
+            ACCOUNT_DELETION_CANCELLED_MESSAGE, CHANGE_PASSWORD_ROUTE,
             EMAIL_IS_ALREADY_TAKEN_MESSAGE, EMAIL_MAX_LENGTH, EMAIL_TOO_LONG_MESSAGE,
             FIELD_REQUIRED_MESSAGE, HOME_ROUTE, INVALID_CREDENTIALS_MESSAGE, INVALID_EMAIL_MESSAGE,
-            PASSWORD_MAX_LENGTH, PASSWORD_MIN_LENGTH, PASSWORD_MISMATCH_MESSAGE,
-            PASSWORD_TOO_LONG_MESSAGE, PASSWORD_TOO_SHORT_MESSAGE, PROTECTED_ROUTE,
+            INVALID_PASSWORD_MESSAGE, PASSWORD_BREACHED_MESSAGE,
+            PASSWORD_BREACH_CHECK_FAILED_MESSAGE, PASSWORD_CONTAINS_EMAIL_MESSAGE,
+            PASSWORD_HASHING_RETRY_AFTER, PASSWORD_MAX_LENGTH, PASSWORD_MIN_LENGTH,
+            PASSWORD_MISMATCH_MESSAGE, PASSWORD_TOO_COMMON_MESSAGE, PASSWORD_TOO_LONG_MESSAGE,
+            PASSWORD_TOO_SHORT_MESSAGE, PASSWORD_TOO_WEAK_MESSAGE, PROTECTED_ROUTE,
+            REAUTHENTICATE_ROUTE, SIGNED_OUT_MESSAGE, SIGNIN_ROUTE, TOO_MANY_REQUESTS_MESSAGE,
+            USER_AGENT_MAX_LENGTH,
         },
+        error::AppError,
         middleware::RenderOptions,
-        response::{create_client_side_redirect, create_redirect_for_authenticated},
+        response::{create_redirect_after_submission, create_redirect_for_authenticated},
     },
     controllers::auth::{
-        sign_in, sign_out, sign_up, SigninData, SigninError, SignupData, SignupError,
+        is_email_available, reauthenticate, sign_in, sign_out, sign_up, ReauthenticateError,
+        SigninData, SigninError, SignupData, SignupError,
     },
     libs::{
-        auth::{is_anonymous, AuthSession},
-        validation::is_valid_email,
+        auth::{is_anonymous, AuthSession, Backend},
+        breached_password::count_password_breaches,
+        flash::Flash,
+        password_policy::{
+            estimate_password_strength, PasswordPolicy, PasswordPolicyViolation, PasswordStrength,
+        },
+        validation::{is_local_path, is_valid_email},
     },
+    metrics::record_password_breach_check_failure,
     state::AppState,
 };
 use askama_axum::Template;
 use axum::{
-    extract::{Extension, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    extract::{ConnectInfo, Extension, Query, State},
+    http::{
+        header::{RETRY_AFTER, USER_AGENT},
+        HeaderMap, HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
     routing::{get, post},
     Form, Router,
 };
-use axum_login::predicate_required;
+use axum_login::{login_required, predicate_required};
 use serde::Deserialize;
-use tracing::error;
+use std::net::SocketAddr;
+use tracing::{instrument, warn};
+use urlencoding::encode;
 
 pub fn create_auth_router() -> Router<AppState> {
     Router::new()
@@ -41,6 +60,16 @@ pub fn create_auth_router() -> Router<AppState> {
                 ))
                 .post(post_signup),
         )
+        .route("/signup/email", post(post_signup_email))
+        .route("/signup/password", post(post_signup_password))
+        .route(
+            "/signup/password-strength",
+            post(post_signup_password_strength),
+        )
+        .route(
+            "/signup/confirm-password",
+            post(post_signup_confirm_password),
+        )
         .route(
             "/signin",
             get(get_signin)
@@ -51,6 +80,12 @@ pub fn create_auth_router() -> Router<AppState> {
                 .post(post_signin),
         )
         .route("/signout", post(post_signout))
+        .route(
+            REAUTHENTICATE_ROUTE,
+            get(get_reauthenticate)
+                .post(post_reauthenticate)
+                .layer(login_required!(Backend, login_url = SIGNIN_ROUTE)),
+        )
 }
 
 #[derive(Template)]
@@ -93,6 +128,7 @@ impl SignupFormErrors<'_> {
     }
 }
 
+#[instrument(skip_all)]
 async fn get_signup(Extension(options): Extension<RenderOptions>) -> SignupTemplate<'static> {
     SignupTemplate {
         options,
@@ -113,14 +149,41 @@ struct SignupFormTemplate<'a> {
     form_data: SignupFormData<'a>,
 }
 
+fn render_signup_form(
+    options: RenderOptions,
+    status_code: StatusCode,
+    form_data: SignupFormData,
+) -> Response {
+    if options.use_base_layout {
+        let template = SignupTemplate { options, form_data };
+        return (status_code, template).into_response();
+    }
+    let template = SignupFormTemplate { form_data };
+    (status_code, template).into_response()
+}
+
+#[instrument(skip_all)]
 async fn post_signup(
     State(state): State<AppState>,
+    Extension(options): Extension<RenderOptions>,
     mut auth_session: AuthSession,
     Form(payload): Form<SignupPayload>,
 ) -> impl IntoResponse {
-    if let Err(form_data) = validate_signup_payload(&payload) {
-        let template = SignupFormTemplate { form_data };
-        return (StatusCode::UNPROCESSABLE_ENTITY, template).into_response();
+    if let Err(form_data) = validate_signup_payload(&payload, &state.password_policy) {
+        return render_signup_form(options, StatusCode::UNPROCESSABLE_ENTITY, form_data);
+    }
+    if let Some(error) = validate_unbreached_password(&state, &payload.password).await {
+        let form_data = SignupFormData {
+            focus: SignupFormField::Password,
+            values: SignupFormValues {
+                email: &payload.email,
+            },
+            errors: SignupFormErrors {
+                password: Some(error),
+                ..Default::default()
+            },
+        };
+        return render_signup_form(options, StatusCode::UNPROCESSABLE_ENTITY, form_data);
     }
     match sign_up(
         SignupData {
@@ -128,6 +191,7 @@ async fn post_signup(
             password: payload.password,
         },
         &state.db,
+        &state.password_hashing,
         &mut auth_session,
     )
     .await
@@ -144,49 +208,38 @@ async fn post_signup(
                     },
                     ..Default::default()
                 };
-                let template = SignupFormTemplate { form_data };
-                (StatusCode::CONFLICT, template).into_response()
+                render_signup_form(options, StatusCode::CONFLICT, form_data)
             }
-            _ => {
-                error!("Failed to sign up: {:?}", e);
-                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            _ if e.is_hashing_busy() => {
+                AppError::service_unavailable("Failed to sign up", PASSWORD_HASHING_RETRY_AFTER)
+                    .into_response()
             }
+            _ => AppError::internal("Failed to sign up", e).into_response(),
         },
-        Ok(_) => create_client_side_redirect(StatusCode::CREATED, PROTECTED_ROUTE).into_response(),
+        Ok(_) => create_redirect_after_submission(&options, StatusCode::CREATED, PROTECTED_ROUTE),
     }
 }
 
-fn validate_signup_payload(payload: &SignupPayload) -> Result<(), SignupFormData> {
+fn validate_signup_payload<'a>(
+    payload: &'a SignupPayload,
+    password_policy: &PasswordPolicy,
+) -> Result<(), SignupFormData<'a>> {
     let mut focus = SignupFormField::default();
     let mut errors = SignupFormErrors::default();
 
-    if payload.confirm_password.is_empty() {
-        errors.confirm_password = Some(FIELD_REQUIRED_MESSAGE);
-        focus = SignupFormField::ConfirmPassword;
-    } else if payload.confirm_password != payload.password {
-        errors.confirm_password = Some(PASSWORD_MISMATCH_MESSAGE);
+    errors.confirm_password =
+        validate_confirm_password(&payload.password, &payload.confirm_password);
+    if errors.confirm_password.is_some() {
         focus = SignupFormField::ConfirmPassword;
     }
 
-    if payload.password.is_empty() {
-        errors.password = Some(FIELD_REQUIRED_MESSAGE);
-        focus = SignupFormField::Password;
-    } else if payload.password.len() < PASSWORD_MIN_LENGTH {
-        errors.password = Some(PASSWORD_TOO_SHORT_MESSAGE);
-        focus = SignupFormField::Password;
-    } else if payload.password.len() > PASSWORD_MAX_LENGTH {
-        errors.password = Some(PASSWORD_TOO_LONG_MESSAGE);
+    errors.password = validate_new_password(&payload.password, &payload.email, password_policy);
+    if errors.password.is_some() {
         focus = SignupFormField::Password;
     }
 
-    if payload.email.is_empty() {
-        errors.email = Some(FIELD_REQUIRED_MESSAGE);
-        focus = SignupFormField::Email;
-    } else if payload.email.len() > EMAIL_MAX_LENGTH {
-        errors.email = Some(EMAIL_TOO_LONG_MESSAGE);
-        focus = SignupFormField::Email;
-    } else if !is_valid_email(&payload.email) {
-        errors.email = Some(INVALID_EMAIL_MESSAGE);
+    errors.email = validate_signup_email(&payload.email);
+    if errors.email.is_some() {
         focus = SignupFormField::Email;
     }
 
@@ -202,6 +255,212 @@ fn validate_signup_payload(payload: &SignupPayload) -> Result<(), SignupFormData
     })
 }
 
+fn validate_signup_email(email: &str) -> Option<&'static str> {
+    if email.is_empty() {
+        Some(FIELD_REQUIRED_MESSAGE)
+    } else if email.len() > EMAIL_MAX_LENGTH {
+        Some(EMAIL_TOO_LONG_MESSAGE)
+    } else if !is_valid_email(email) {
+        Some(INVALID_EMAIL_MESSAGE)
+    } else {
+        None
+    }
+}
+
+// Used wherever users choose a password, the email is needed to reject
+// passwords based on it.
+pub fn validate_new_password(
+    password: &str,
+    email: &str,
+    password_policy: &PasswordPolicy,
+) -> Option<&'static str> {
+    if password.is_empty() {
+        Some(FIELD_REQUIRED_MESSAGE)
+    } else if password.len() < PASSWORD_MIN_LENGTH {
+        Some(PASSWORD_TOO_SHORT_MESSAGE)
+    } else if password.len() > PASSWORD_MAX_LENGTH {
+        Some(PASSWORD_TOO_LONG_MESSAGE)
+    } else {
+        match password_policy.check(password, email) {
+            Ok(()) => None,
+            Err(PasswordPolicyViolation::CommonPassword) => Some(PASSWORD_TOO_COMMON_MESSAGE),
+            Err(PasswordPolicyViolation::ContainsEmail) => Some(PASSWORD_CONTAINS_EMAIL_MESSAGE),
+            Err(PasswordPolicyViolation::TooWeak) => Some(PASSWORD_TOO_WEAK_MESSAGE),
+        }
+    }
+}
+
+// Checked separately from the policy as it may read the breached passwords
+// dataset. When the check fails the password is rejected unless the check is
+// configured to fail open, and the failures are counted to notice them.
+pub async fn validate_unbreached_password(
+    state: &AppState,
+    password: &str,
+) -> Option<&'static str> {
+    let ranges = state.breached_password_ranges.as_deref()?;
+    match count_password_breaches(ranges, password).await {
+        Ok(0) => None,
+        Ok(_) => Some(PASSWORD_BREACHED_MESSAGE),
+        Err(e) => {
+            record_password_breach_check_failure();
+            warn!("Failed to check whether password was breached: {}", e);
+            (!state.breached_password_check_fail_open)
+                .then_some(PASSWORD_BREACH_CHECK_FAILED_MESSAGE)
+        }
+    }
+}
+
+pub fn validate_confirm_password(password: &str, confirm_password: &str) -> Option<&'static str> {
+    if confirm_password.is_empty() {
+        Some(FIELD_REQUIRED_MESSAGE)
+    } else if confirm_password != password {
+        Some(PASSWORD_MISMATCH_MESSAGE)
+    } else {
+        None
+    }
+}
+
+#[derive(Template)]
+#[template(path = "pages/signup/field.html")]
+struct SignupFieldTemplate<'a> {
+    field: SignupFormField,
+    value: &'a str,
+    error: Option<&'a str>,
+}
+
+impl SignupFieldTemplate<'_> {
+    fn status_code(&self) -> StatusCode {
+        match self.error {
+            None => StatusCode::OK,
+            Some(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SignupEmailPayload {
+    email: String,
+}
+
+#[instrument(skip_all)]
+async fn post_signup_email(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Form(payload): Form<SignupEmailPayload>,
+) -> impl IntoResponse {
+    let mut template = SignupFieldTemplate {
+        field: SignupFormField::Email,
+        value: &payload.email,
+        error: validate_signup_email(&payload.email),
+    };
+    if template.error.is_some() {
+        return (template.status_code(), template).into_response();
+    }
+
+    // Limit the availability checks per client, so the endpoint can't be used
+    // to enumerate registered emails. The server always provides the client
+    // address, without it the clients can't be told apart so none is limited.
+    if let Some(ConnectInfo(address)) = connect_info {
+        let client = address.ip().to_string();
+        let limiter = &state.email_availability_limiter;
+        if !limiter.try_acquire(&client) {
+            warn!(
+                "Email availability check rate limit exceeded for {}",
+                client
+            );
+            template.error = Some(TOO_MANY_REQUESTS_MESSAGE);
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, limiter.window().as_secs().to_string())],
+                template,
+            )
+                .into_response();
+        }
+    }
+
+    match is_email_available(&payload.email, &state.db).await {
+        Err(e) => AppError::internal("Failed to check email availability", e).into_response(),
+        Ok(false) => {
+            template.error = Some(EMAIL_IS_ALREADY_TAKEN_MESSAGE);
+            (StatusCode::CONFLICT, template).into_response()
+        }
+        Ok(true) => (template.status_code(), template).into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+struct SignupPasswordPayload {
+    #[serde(default)]
+    email: String,
+    password: String,
+}
+
+#[instrument(skip_all)]
+async fn post_signup_password(
+    State(state): State<AppState>,
+    Form(payload): Form<SignupPasswordPayload>,
+) -> impl IntoResponse {
+    let mut template = SignupFieldTemplate {
+        field: SignupFormField::Password,
+        value: "",
+        error: validate_new_password(&payload.password, &payload.email, &state.password_policy),
+    };
+    if template.error.is_none() {
+        template.error = validate_unbreached_password(&state, &payload.password).await;
+    }
+    (template.status_code(), template).into_response()
+}
+
+const PASSWORD_STRENGTH_LABELS: [&str; 5] = ["Very weak", "Weak", "Fair", "Good", "Strong"];
+
+#[derive(Template)]
+#[template(path = "pages/signup/password-strength.html")]
+struct PasswordStrengthTemplate {
+    strength: Option<PasswordStrength>,
+    label: &'static str,
+    // Passwords scoring less are rejected by the policy
+    min_score: u8,
+    min_score_label: &'static str,
+}
+
+fn get_password_strength_label(score: u8) -> &'static str {
+    PASSWORD_STRENGTH_LABELS[usize::from(score).min(PASSWORD_STRENGTH_LABELS.len() - 1)]
+}
+
+#[instrument(skip_all)]
+async fn post_signup_password_strength(
+    State(state): State<AppState>,
+    Form(payload): Form<SignupPasswordPayload>,
+) -> PasswordStrengthTemplate {
+    let strength = (!payload.password.is_empty())
+        .then(|| estimate_password_strength(&payload.password, &payload.email));
+    let min_score = state.password_policy.min_score;
+    PasswordStrengthTemplate {
+        label: get_password_strength_label(strength.as_ref().map_or(0, |strength| strength.score)),
+        strength,
+        min_score,
+        min_score_label: get_password_strength_label(min_score),
+    }
+}
+
+#[derive(Deserialize)]
+struct SignupConfirmPasswordPayload {
+    password: String,
+    confirm_password: String,
+}
+
+#[instrument(skip_all)]
+async fn post_signup_confirm_password(
+    Form(payload): Form<SignupConfirmPasswordPayload>,
+) -> impl IntoResponse {
+    let template = SignupFieldTemplate {
+        field: SignupFormField::ConfirmPassword,
+        value: "",
+        error: validate_confirm_password(&payload.password, &payload.confirm_password),
+    };
+    (template.status_code(), template).into_response()
+}
+
 #[derive(Deserialize)]
 struct SigninParams {
     next: Option<String>,
@@ -231,6 +490,7 @@ enum SigninFormField {
 #[derive(Default)]
 struct SigninFormValues<'a> {
     email: &'a str,
+    remember_me: bool,
     next: Option<&'a str>,
 }
 
@@ -247,6 +507,7 @@ impl SigninFormErrors<'_> {
     }
 }
 
+#[instrument(skip_all)]
 async fn get_signin(
     Extension(options): Extension<RenderOptions>,
     params: Query<SigninParams>,
@@ -268,6 +529,8 @@ async fn get_signin(
 struct SigninPayload {
     email: String,
     password: String,
+    // Unchecked checkboxes aren't sent at all
+    remember_me: Option<String>,
     next: Option<String>,
 }
 
@@ -277,49 +540,86 @@ struct SigninFormTemplate<'a> {
     form_data: SigninFormData<'a>,
 }
 
+fn render_signin_form(
+    options: RenderOptions,
+    status_code: StatusCode,
+    form_data: SigninFormData,
+) -> Response {
+    if options.use_base_layout {
+        let template = SigninTemplate { options, form_data };
+        return (status_code, template).into_response();
+    }
+    let template = SigninFormTemplate { form_data };
+    (status_code, template).into_response()
+}
+
+#[instrument(skip_all)]
 async fn post_signin(
+    State(state): State<AppState>,
+    Extension(options): Extension<RenderOptions>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
     mut auth_session: AuthSession,
+    flash: Flash,
     Form(payload): Form<SigninPayload>,
 ) -> impl IntoResponse {
     if let Err(form_data) = validate_signin_payload(&payload) {
-        let template = SigninFormTemplate { form_data };
-        return (StatusCode::UNPROCESSABLE_ENTITY, template).into_response();
+        return render_signin_form(options, StatusCode::UNPROCESSABLE_ENTITY, form_data);
     }
 
     match sign_in(
         SigninData {
             email: payload.email.clone(),
             password: payload.password,
+            remember_me: payload.remember_me.is_some(),
+            ip_address: connect_info.map(|ConnectInfo(address)| address.ip().to_string()),
+            user_agent: headers
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.chars().take(USER_AGENT_MAX_LENGTH).collect()),
         },
+        &state.db,
+        &state.password_rotation,
         &mut auth_session,
     )
     .await
     {
         Err(e) => match e {
             SigninError::InvalidCredentialsError => {
-                let template = SigninFormTemplate {
-                    form_data: SigninFormData {
-                        values: SigninFormValues {
-                            email: &payload.email,
-                            next: payload.next.as_deref(),
-                        },
-                        errors: SigninFormErrors {
-                            general: Some(INVALID_CREDENTIALS_MESSAGE),
-                            ..Default::default()
-                        },
+                let form_data = SigninFormData {
+                    values: SigninFormValues {
+                        email: &payload.email,
+                        remember_me: payload.remember_me.is_some(),
+                        next: payload.next.as_deref(),
+                    },
+                    errors: SigninFormErrors {
+                        general: Some(INVALID_CREDENTIALS_MESSAGE),
                         ..Default::default()
                     },
+                    ..Default::default()
                 };
-                (StatusCode::UNAUTHORIZED, template).into_response()
+                render_signin_form(options, StatusCode::UNAUTHORIZED, form_data)
             }
-            _ => {
-                error!("Failed to sign in: {:?}", e);
-                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            _ if e.is_hashing_busy() => {
+                AppError::service_unavailable("Failed to sign in", PASSWORD_HASHING_RETRY_AFTER)
+                    .into_response()
             }
+            _ => AppError::internal("Failed to sign in", e).into_response(),
         },
-        Ok(_) => {
-            let next_url = payload.next.as_deref().unwrap_or(PROTECTED_ROUTE);
-            create_client_side_redirect(StatusCode::OK, next_url).into_response()
+        Ok(outcome) => {
+            if outcome.deletion_cancelled {
+                flash.info(ACCOUNT_DELETION_CANCELLED_MESSAGE).await;
+            }
+            let next_url = payload
+                .next
+                .as_deref()
+                .filter(|next| is_local_path(next))
+                .unwrap_or(PROTECTED_ROUTE);
+            if outcome.must_change_password {
+                let url = format!("{}?next={}", CHANGE_PASSWORD_ROUTE, encode(next_url));
+                return create_redirect_after_submission(&options, StatusCode::OK, &url);
+            }
+            create_redirect_after_submission(&options, StatusCode::OK, next_url)
         }
     }
 }
@@ -346,6 +646,7 @@ fn validate_signin_payload(payload: &SigninPayload) -> Result<(), SigninFormData
             focus,
             values: SigninFormValues {
                 email: &payload.email,
+                remember_me: payload.remember_me.is_some(),
                 next: payload.next.as_deref(),
             },
             errors,
@@ -354,12 +655,134 @@ fn validate_signin_payload(payload: &SigninPayload) -> Result<(), SigninFormData
     Ok(())
 }
 
-async fn post_signout(mut auth_session: AuthSession) -> impl IntoResponse {
+#[instrument(skip_all)]
+async fn post_signout(
+    Extension(options): Extension<RenderOptions>,
+    mut auth_session: AuthSession,
+    flash: Flash,
+) -> impl IntoResponse {
     match sign_out(&mut auth_session).await {
-        Err(e) => {
-            error!("Failed to sign out: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        Err(e) => AppError::internal("Failed to sign out", e).into_response(),
+        Ok(_) => {
+            flash.success(SIGNED_OUT_MESSAGE).await;
+            create_redirect_after_submission(&options, StatusCode::NO_CONTENT, HOME_ROUTE)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ReauthenticateParams {
+    next: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/reauthenticate/index.html")]
+struct ReauthenticateTemplate<'a> {
+    options: RenderOptions,
+    form_data: ReauthenticateFormData<'a>,
+}
+
+#[derive(Default)]
+struct ReauthenticateFormData<'a> {
+    next: Option<&'a str>,
+    error: Option<&'a str>,
+}
+
+#[instrument(skip_all)]
+async fn get_reauthenticate(
+    Extension(options): Extension<RenderOptions>,
+    params: Query<ReauthenticateParams>,
+) -> impl IntoResponse {
+    ReauthenticateTemplate {
+        options,
+        form_data: ReauthenticateFormData {
+            next: params.next.as_deref(),
+            ..Default::default()
+        },
+    }
+    .into_response()
+}
+
+#[derive(Deserialize)]
+struct ReauthenticatePayload {
+    password: String,
+    next: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "pages/reauthenticate/form.html")]
+struct ReauthenticateFormTemplate<'a> {
+    form_data: ReauthenticateFormData<'a>,
+}
+
+fn render_reauthenticate_form(
+    options: RenderOptions,
+    status_code: StatusCode,
+    form_data: ReauthenticateFormData,
+) -> Response {
+    if options.use_base_layout {
+        let template = ReauthenticateTemplate { options, form_data };
+        return (status_code, template).into_response();
+    }
+    let template = ReauthenticateFormTemplate { form_data };
+    (status_code, template).into_response()
+}
+
+#[instrument(skip_all)]
+async fn post_reauthenticate(
+    Extension(options): Extension<RenderOptions>,
+    State(state): State<AppState>,
+    auth_session: AuthSession,
+    Form(payload): Form<ReauthenticatePayload>,
+) -> impl IntoResponse {
+    let Some(user_id) = auth_session.user.as_ref().map(|user| user.id) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    if payload.password.is_empty() {
+        let form_data = ReauthenticateFormData {
+            next: payload.next.as_deref(),
+            error: Some(FIELD_REQUIRED_MESSAGE),
+        };
+        return render_reauthenticate_form(options, StatusCode::UNPROCESSABLE_ENTITY, form_data);
+    }
+
+    // Limit the password checks per user, so a hijacked session can't be used
+    // to guess the password.
+    let limiter = &state.reauthentication_limiter;
+    if !limiter.try_acquire(&user_id.to_string()) {
+        warn!("Re-authentication rate limit exceeded for user {}", user_id);
+        let form_data = ReauthenticateFormData {
+            next: payload.next.as_deref(),
+            error: Some(TOO_MANY_REQUESTS_MESSAGE),
+        };
+        let mut response =
+            render_reauthenticate_form(options, StatusCode::TOO_MANY_REQUESTS, form_data);
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(limiter.window().as_secs()));
+        return response;
+    }
+
+    match reauthenticate(payload.password, &state.password_hashing, &auth_session).await {
+        Err(ReauthenticateError::InvalidPasswordError) => {
+            let form_data = ReauthenticateFormData {
+                next: payload.next.as_deref(),
+                error: Some(INVALID_PASSWORD_MESSAGE),
+            };
+            render_reauthenticate_form(options, StatusCode::UNAUTHORIZED, form_data)
+        }
+        Err(e) if e.is_hashing_busy() => {
+            AppError::service_unavailable("Failed to re-authenticate", PASSWORD_HASHING_RETRY_AFTER)
+                .into_response()
+        }
+        Err(e) => AppError::internal("Failed to re-authenticate", e).into_response(),
+        Ok(_) => {
+            let next_url = payload
+                .next
+                .as_deref()
+                .filter(|next| is_local_path(next))
+                .unwrap_or(PROTECTED_ROUTE);
+            create_redirect_after_submission(&options, StatusCode::OK, next_url)
         }
-        Ok(_) => create_client_side_redirect(StatusCode::NO_CONTENT, HOME_ROUTE).into_response(),
     }
 }
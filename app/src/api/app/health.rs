@@ -0,0 +1,53 @@
+use crate::{
+    controllers::health::{check_readiness, ReadinessReport},
+    state::AppState,
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::Serialize;
+
+pub fn create_health_router() -> Router<AppState> {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+}
+
+// The process is able to serve requests, dependencies aren't checked
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+#[derive(Serialize)]
+struct ReadinessResponse {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checks: Option<ReadinessReport>,
+}
+
+async fn readyz(State(state): State<AppState>) -> Response {
+    // Report not ready as soon as the shutdown starts, so no new traffic is
+    // routed while the connections are drained.
+    if state.readiness.is_shutting_down() {
+        let response = ReadinessResponse {
+            status: "shutting_down",
+            checks: None,
+        };
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(response)).into_response();
+    }
+    let report = check_readiness(&state.db).await;
+    let (status_code, status) = if report.is_ready() {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "not_ready")
+    };
+    let response = ReadinessResponse {
+        status,
+        checks: Some(report),
+    };
+    (status_code, Json(response)).into_response()
+}
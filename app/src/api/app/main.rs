@@ -6,6 +6,7 @@ use crate::{
 use askama_axum::Template;
 use axum::{extract::Extension, http::StatusCode, response::IntoResponse, routing::get, Router};
 use axum_login::predicate_required;
+use tracing::instrument;
 
 pub fn create_main_router() -> Router<AppState> {
     Router::new()
@@ -22,6 +23,7 @@ struct HomeTemplate {
     options: RenderOptions,
 }
 
+#[instrument(skip_all)]
 async fn home(Extension(options): Extension<RenderOptions>) -> HomeTemplate {
     HomeTemplate { options }
 }
@@ -32,6 +34,7 @@ pub struct NotFoundTemplate {
     options: RenderOptions,
 }
 
+#[instrument(skip_all)]
 pub async fn handler_404(Extension(options): Extension<RenderOptions>) -> impl IntoResponse {
     let template = NotFoundTemplate { options };
     (StatusCode::NOT_FOUND, template).into_response()
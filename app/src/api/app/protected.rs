@@ -1,20 +1,31 @@
 use crate::{
     api::{
+        app::account::PASSWORD_CHANGE_REDIRECT,
         constant::SIGNIN_ROUTE,
         middleware::{set_default_response_headers_for_protected, RenderOptions},
     },
-    libs::auth::Backend,
+    libs::{auth::Backend, password_rotation::enforce_password_change},
     state::AppState,
 };
 use askama_axum::Template;
-use axum::{extract::Extension, middleware::map_response, routing::get, Router};
+use axum::{
+    extract::Extension,
+    middleware::{from_fn_with_state, map_response},
+    routing::get,
+    Router,
+};
 use axum_login::login_required;
 use tower::ServiceBuilder;
+use tracing::instrument;
 
 pub fn create_protected_router() -> Router<AppState> {
     Router::new().route("/protected", get(protected)).layer(
         ServiceBuilder::new()
             .layer(login_required!(Backend, login_url = SIGNIN_ROUTE))
+            .layer(from_fn_with_state(
+                PASSWORD_CHANGE_REDIRECT,
+                enforce_password_change,
+            ))
             .layer(map_response(set_default_response_headers_for_protected)),
     )
 }
@@ -25,6 +36,7 @@ struct ProtectedTemplate {
     options: RenderOptions,
 }
 
+#[instrument(skip_all)]
 async fn protected(Extension(options): Extension<RenderOptions>) -> ProtectedTemplate {
     ProtectedTemplate { options }
 }
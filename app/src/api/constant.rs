@@ -1,6 +1,17 @@
+use std::time::Duration;
+
 pub const EMAIL_MAX_LENGTH: usize = 254;
 pub const PASSWORD_MIN_LENGTH: usize = 8;
 pub const PASSWORD_MAX_LENGTH: usize = 256;
+pub const USER_AGENT_MAX_LENGTH: usize = 512;
+
+pub const EMAIL_AVAILABILITY_CHECK_LIMIT: u32 = 10;
+pub const EMAIL_AVAILABILITY_CHECK_WINDOW: Duration = Duration::from_secs(60);
+
+pub const REAUTHENTICATION_LIMIT: u32 = 5;
+pub const REAUTHENTICATION_WINDOW: Duration = Duration::from_secs(300);
+
+pub const PASSWORD_HASHING_RETRY_AFTER: Duration = Duration::from_secs(1);
 
 // TODO: Come up with better messages handling e.g. i18
 pub const FIELD_REQUIRED_MESSAGE: &str = "This field is required";
@@ -9,9 +20,37 @@ pub const INVALID_EMAIL_MESSAGE: &str = "Invalid email";
 pub const PASSWORD_TOO_SHORT_MESSAGE: &str = "Password must be at least 8 characters";
 pub const PASSWORD_TOO_LONG_MESSAGE: &str = "Password must be at most 256 characters";
 pub const PASSWORD_MISMATCH_MESSAGE: &str = "Password doesn't match";
+pub const PASSWORD_TOO_COMMON_MESSAGE: &str = "This password is too common";
+pub const PASSWORD_CONTAINS_EMAIL_MESSAGE: &str = "Password must not contain your email";
+pub const PASSWORD_TOO_WEAK_MESSAGE: &str = "Password is too easy to guess";
+pub const PASSWORD_BREACHED_MESSAGE: &str =
+    "This password appeared in a data breach, please choose another one";
+pub const PASSWORD_BREACH_CHECK_FAILED_MESSAGE: &str =
+    "This password couldn't be checked against data breaches, please try again later";
+pub const PASSWORD_REUSED_MESSAGE: &str =
+    "You have used this password recently, please choose another one";
 pub const EMAIL_IS_ALREADY_TAKEN_MESSAGE: &str = "Email is already taken";
 pub const INVALID_CREDENTIALS_MESSAGE: &str = "Incorrect email or password";
+pub const INVALID_PASSWORD_MESSAGE: &str = "Incorrect password";
+pub const SIGNED_OUT_MESSAGE: &str = "You have been signed out";
+pub const PASSWORD_CHANGED_MESSAGE: &str = "Your password has been changed";
+pub const ACCOUNT_DELETION_SCHEDULED_MESSAGE: &str =
+    "Your account will be deleted, sign in before then to cancel the deletion";
+pub const ACCOUNT_DELETION_CANCELLED_MESSAGE: &str =
+    "Welcome back, the deletion of your account has been cancelled";
+pub const SESSION_EXPIRED_MESSAGE: &str = "Your session has expired, please sign in again";
+pub const TOO_MANY_REQUESTS_MESSAGE: &str = "Too many attempts, please try again later";
+
+pub const PAGE_CONTENT_SELECTOR: &str = "#page";
+// Reloads the data export status, see `pages/data-export/status.html`
+pub const DATA_EXPORT_REQUESTED_EVENT: &str = "data-export-requested";
 
 pub const HOME_ROUTE: &str = "/";
 pub const SIGNIN_ROUTE: &str = "/signin";
+pub const REAUTHENTICATE_ROUTE: &str = "/reauthenticate";
+pub const CHANGE_PASSWORD_ROUTE: &str = "/account/password";
+pub const DELETE_ACCOUNT_ROUTE: &str = "/account/delete";
+pub const DATA_EXPORT_ROUTE: &str = "/account/export";
+pub const DATA_EXPORT_STATUS_ROUTE: &str = "/account/export/status";
+pub const DATA_EXPORT_DOWNLOAD_ROUTE: &str = "/account/export/download";
 pub const PROTECTED_ROUTE: &str = "/protected";
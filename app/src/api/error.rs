@@ -0,0 +1,293 @@
+use crate::api::{constant::PAGE_CONTENT_SELECTOR, htmx::HtmxResponse, middleware::RenderOptions};
+use askama_axum::Template;
+use axum::{
+    body::Body,
+    extract::{Extension, Request},
+    http::{
+        header::{CONTENT_TYPE, RETRY_AFTER},
+        HeaderValue, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    any::Any,
+    error::Error,
+    fmt::{Debug as FormatDebug, Display, Formatter, Result as FormatResult},
+    panic::{catch_unwind, AssertUnwindSafe},
+    time::Duration,
+};
+use tower_http::request_id::RequestId;
+use tracing::{error, warn};
+
+const HTML_CONTENT_TYPE: &str = "text/html; charset=utf-8";
+
+pub enum AppError {
+    InternalError {
+        context: &'static str,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    // The server is overloaded, the request can be retried once it catches up
+    ServiceUnavailableError {
+        context: &'static str,
+        retry_after: Duration,
+    },
+}
+
+impl AppError {
+    pub fn internal(context: &'static str, source: impl Error + Send + Sync + 'static) -> Self {
+        AppError::InternalError {
+            context,
+            source: Box::new(source),
+        }
+    }
+
+    pub fn service_unavailable(context: &'static str, retry_after: Duration) -> Self {
+        AppError::ServiceUnavailableError {
+            context,
+            retry_after,
+        }
+    }
+}
+
+impl FormatDebug for AppError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        match self {
+            AppError::InternalError { context, source } => {
+                write!(f, "{}: {:?}", context, source)
+            }
+            AppError::ServiceUnavailableError { context, .. } => {
+                write!(f, "{}: service unavailable", context)
+            }
+        }
+    }
+}
+
+// The error is logged within the request span, so it can be found by the
+// request ID shown on the error page.
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        match self {
+            AppError::InternalError { .. } => {
+                error!("{:?}", self);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+            AppError::ServiceUnavailableError { retry_after, .. } => {
+                warn!("{:?}", self);
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [(RETRY_AFTER, retry_after.as_secs().to_string())],
+                )
+                    .into_response()
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HandlerPanicError(String);
+
+impl Error for HandlerPanicError {}
+
+impl Display for HandlerPanicError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        write!(f, "Panic: {}", self.0)
+    }
+}
+
+pub fn handle_panic(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let details = if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else {
+        String::from("Unknown panic message")
+    };
+    AppError::internal("Request handler panicked", HandlerPanicError(details)).into_response()
+}
+
+#[derive(Template)]
+#[template(path = "pages/403.html")]
+struct ForbiddenTemplate {
+    options: RenderOptions,
+}
+
+#[derive(Template)]
+#[template(path = "pages/429.html")]
+struct TooManyRequestsTemplate {
+    options: RenderOptions,
+}
+
+#[derive(Template)]
+#[template(path = "pages/503.html")]
+struct ServiceUnavailableTemplate {
+    options: RenderOptions,
+}
+
+#[derive(Template)]
+#[template(path = "pages/500.html")]
+struct InternalServerErrorTemplate {
+    options: RenderOptions,
+    request_id: Option<String>,
+}
+
+struct ErrorPage {
+    status_code: StatusCode,
+    request_id: Option<String>,
+}
+
+// Only bare error responses get a page, the ones with a body (e.g. form
+// fragments or extractor rejections) already explain what went wrong.
+fn get_error_page(response: &Response, request_id: Option<String>) -> Option<ErrorPage> {
+    let status_code = response.status();
+    let has_page = matches!(
+        status_code,
+        StatusCode::FORBIDDEN
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::SERVICE_UNAVAILABLE
+    );
+    if !has_page || response.headers().contains_key(CONTENT_TYPE) {
+        return None;
+    }
+    Some(ErrorPage {
+        status_code,
+        request_id,
+    })
+}
+
+pub async fn render_error_pages(
+    Extension(options): Extension<RenderOptions>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|request_id| request_id.header_value().to_str().ok())
+        .map(str::to_owned);
+    let response = next.run(request).await;
+    let Some(error_page) = get_error_page(&response, request_id) else {
+        return response;
+    };
+
+    let use_base_layout = options.use_base_layout;
+    let (mut parts, _) = response.into_parts();
+    parts
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static(HTML_CONTENT_TYPE));
+    let body = render_error_page(&error_page, options);
+    let response = Response::from_parts(parts, Body::from(body));
+    if use_base_layout {
+        return response;
+    }
+    // Show the error in place of the page, whatever the htmx request targeted,
+    // but keep the URL of that page, so reloading doesn't repeat the request
+    (
+        HtmxResponse::new()
+            .retarget(PAGE_CONTENT_SELECTOR)
+            .reswap("innerHTML")
+            .push_url("false"),
+        response,
+    )
+        .into_response()
+}
+
+fn render_error_page(error_page: &ErrorPage, options: RenderOptions) -> String {
+    let request_id = error_page.request_id.clone();
+    // Rendering may panic as well (e.g. missing assets in the base layout), so
+    // fall back to plain text to always return some explanation to the user.
+    let rendered = catch_unwind(AssertUnwindSafe(|| match error_page.status_code {
+        StatusCode::FORBIDDEN => ForbiddenTemplate { options }.render(),
+        StatusCode::TOO_MANY_REQUESTS => TooManyRequestsTemplate { options }.render(),
+        StatusCode::SERVICE_UNAVAILABLE => ServiceUnavailableTemplate { options }.render(),
+        _ => InternalServerErrorTemplate {
+            options,
+            request_id,
+        }
+        .render(),
+    }));
+    match rendered {
+        Ok(Ok(body)) => body,
+        Ok(Err(e)) => {
+            error!("Failed to render error page: {:?}", e);
+            create_plain_error_message(error_page)
+        }
+        Err(_) => {
+            error!("Rendering error page panicked");
+            create_plain_error_message(error_page)
+        }
+    }
+}
+
+fn create_plain_error_message(error_page: &ErrorPage) -> String {
+    let reason = error_page.status_code.canonical_reason().unwrap_or("Error");
+    match &error_page.request_id {
+        None => reason.to_owned(),
+        Some(request_id) => format!("{} (reference: {})", reason, request_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_plain_error_message, get_error_page, handle_panic, AppError, ErrorPage};
+    use axum::{
+        http::{
+            header::{CONTENT_TYPE, RETRY_AFTER},
+            StatusCode,
+        },
+        response::IntoResponse,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn internal_error_is_converted_to_bare_response() {
+        let error = std::io::Error::other("failure");
+
+        let response = AppError::internal("Failed", error).into_response();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!response.headers().contains_key(CONTENT_TYPE));
+    }
+
+    #[test]
+    fn service_unavailable_error_has_retry_after() {
+        let response =
+            AppError::service_unavailable("Overloaded", Duration::from_secs(1)).into_response();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "1");
+        assert!(!response.headers().contains_key(CONTENT_TYPE));
+    }
+
+    #[test]
+    fn panic_is_converted_to_internal_error() {
+        let response = handle_panic(Box::new("panic message"));
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn error_page_is_rendered_only_for_bare_responses() {
+        let bare_response = StatusCode::FORBIDDEN.into_response();
+        let response_with_body =
+            (StatusCode::TOO_MANY_REQUESTS, [(CONTENT_TYPE, "text/html")]).into_response();
+        let not_handled_response = StatusCode::NOT_FOUND.into_response();
+
+        assert!(get_error_page(&bare_response, None).is_some());
+        assert!(get_error_page(&response_with_body, None).is_none());
+        assert!(get_error_page(&not_handled_response, None).is_none());
+    }
+
+    #[test]
+    fn plain_error_message_contains_request_id() {
+        let error_page = ErrorPage {
+            status_code: StatusCode::INTERNAL_SERVER_ERROR,
+            request_id: Some(String::from("123")),
+        };
+
+        let message = create_plain_error_message(&error_page);
+
+        assert_eq!(message, "Internal Server Error (reference: 123)");
+    }
+}
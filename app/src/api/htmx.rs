@@ -0,0 +1,306 @@
+use async_trait::async_trait;
+use axum::{
+    extract::FromRequestParts,
+    http::{header::CONTENT_TYPE, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponseParts, Response, ResponseParts},
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::convert::Infallible;
+
+const HX_REQUEST: &str = "HX-Request";
+const HX_BOOSTED: &str = "HX-Boosted";
+const HX_TARGET: &str = "HX-Target";
+const HX_TRIGGER: &str = "HX-Trigger";
+const HX_TRIGGER_NAME: &str = "HX-Trigger-Name";
+const HX_CURRENT_URL: &str = "HX-Current-URL";
+const HX_HISTORY_RESTORE_REQUEST: &str = "HX-History-Restore-Request";
+
+const HX_LOCATION: &str = "HX-Location";
+const HX_PUSH_URL: &str = "HX-Push-Url";
+const HX_REDIRECT: &str = "HX-Redirect";
+const HX_REFRESH: &str = "HX-Refresh";
+const HX_RESWAP: &str = "HX-Reswap";
+const HX_RETARGET: &str = "HX-Retarget";
+
+#[derive(Clone, Debug, Default)]
+pub struct HtmxRequest {
+    pub is_htmx: bool,
+    pub boosted: bool,
+    pub history_restore: bool,
+    pub target: Option<String>,
+    pub trigger: Option<String>,
+    pub trigger_name: Option<String>,
+    pub current_url: Option<String>,
+}
+
+impl HtmxRequest {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Self {
+            is_htmx: is_header_true(headers, HX_REQUEST),
+            boosted: is_header_true(headers, HX_BOOSTED),
+            history_restore: is_header_true(headers, HX_HISTORY_RESTORE_REQUEST),
+            target: read_header(headers, HX_TARGET),
+            trigger: read_header(headers, HX_TRIGGER),
+            trigger_name: read_header(headers, HX_TRIGGER_NAME),
+            current_url: read_header(headers, HX_CURRENT_URL),
+        }
+    }
+
+    // Boosted and history restore requests swap the whole page, so only the
+    // remaining htmx requests can be answered with a fragment.
+    pub fn expects_fragment(&self) -> bool {
+        self.is_htmx && !self.boosted && !self.history_restore
+    }
+}
+
+fn is_header_true(headers: &HeaderMap, name: &str) -> bool {
+    headers
+        .get(name)
+        .map_or(false, |value| value.as_bytes() == b"true")
+}
+
+fn read_header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_owned())
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for HtmxRequest
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(HtmxRequest::from_headers(&parts.headers))
+    }
+}
+
+// htmx swaps the body of successful responses, unless they send the browser
+// somewhere else
+pub fn is_swapped_response(response: &Response) -> bool {
+    let headers = response.headers();
+    response.status().is_success()
+        && response.status() != StatusCode::NO_CONTENT
+        && headers
+            .get(CONTENT_TYPE)
+            .map_or(false, |value| value.as_bytes().starts_with(b"text/html"))
+        && ![HX_LOCATION, HX_REDIRECT, HX_REFRESH]
+            .iter()
+            .any(|name| headers.contains_key(*name))
+}
+
+#[derive(Serialize)]
+pub struct HtmxLocation<'a> {
+    path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<&'a str>,
+}
+
+impl<'a> HtmxLocation<'a> {
+    pub fn new(path: &'a str) -> Self {
+        Self { path, target: None }
+    }
+
+    pub fn target(mut self, target: &'a str) -> Self {
+        self.target = Some(target);
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct HtmxResponse {
+    location: Option<String>,
+    triggers: Map<String, Value>,
+    retarget: Option<String>,
+    reswap: Option<String>,
+    push_url: Option<String>,
+    refresh: bool,
+    out_of_band_swaps: Vec<String>,
+}
+
+impl HtmxResponse {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn location(mut self, location: HtmxLocation) -> Self {
+        self.location = Some(serde_json::to_string(&location).expect("Invalid htmx location"));
+        self
+    }
+
+    pub fn trigger(self, event: &str) -> Self {
+        self.trigger_with_detail(event, Value::Null)
+    }
+
+    pub fn trigger_with_detail(mut self, event: &str, detail: impl Serialize) -> Self {
+        let detail = serde_json::to_value(detail).expect("Invalid htmx event detail");
+        self.triggers.insert(event.to_owned(), detail);
+        self
+    }
+
+    pub fn retarget(mut self, selector: &str) -> Self {
+        self.retarget = Some(selector.to_owned());
+        self
+    }
+
+    pub fn reswap(mut self, swap: &str) -> Self {
+        self.reswap = Some(swap.to_owned());
+        self
+    }
+
+    pub fn push_url(mut self, url: &str) -> Self {
+        self.push_url = Some(url.to_owned());
+        self
+    }
+
+    pub fn refresh(mut self) -> Self {
+        self.refresh = true;
+        self
+    }
+
+    // The content must carry the `hx-swap-oob` attribute, it's appended to the
+    // response body by the `append_out_of_band_swaps` middleware.
+    pub fn out_of_band_swap(mut self, content: impl Into<String>) -> Self {
+        self.out_of_band_swaps.push(content.into());
+        self
+    }
+}
+
+#[derive(Clone)]
+pub struct OutOfBandSwaps(pub Vec<String>);
+
+impl IntoResponseParts for HtmxResponse {
+    type Error = (StatusCode, String);
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        let mut headers = Vec::new();
+        if let Some(location) = self.location {
+            headers.push((HX_LOCATION, location));
+        }
+        if !self.triggers.is_empty() {
+            headers.push((HX_TRIGGER, Value::Object(self.triggers).to_string()));
+        }
+        if let Some(retarget) = self.retarget {
+            headers.push((HX_RETARGET, retarget));
+        }
+        if let Some(reswap) = self.reswap {
+            headers.push((HX_RESWAP, reswap));
+        }
+        if let Some(push_url) = self.push_url {
+            headers.push((HX_PUSH_URL, push_url));
+        }
+        if self.refresh {
+            headers.push((HX_REFRESH, String::from("true")));
+        }
+        for (name, value) in headers {
+            let value = HeaderValue::try_from(value).map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Invalid {} header value: {}", name, e),
+                )
+            })?;
+            res.headers_mut().insert(name, value);
+        }
+        if !self.out_of_band_swaps.is_empty() {
+            res.extensions_mut()
+                .insert(OutOfBandSwaps(self.out_of_band_swaps));
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_swapped_response, HtmxLocation, HtmxRequest, HtmxResponse, OutOfBandSwaps};
+    use axum::{
+        http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+        response::IntoResponse,
+    };
+
+    #[test]
+    fn only_plain_htmx_request_expects_fragment() {
+        let cases = [
+            (vec![], false),
+            (vec![("HX-Request", "true")], true),
+            (vec![("HX-Request", "true"), ("HX-Boosted", "true")], false),
+            (
+                vec![
+                    ("HX-Request", "true"),
+                    ("HX-History-Restore-Request", "true"),
+                ],
+                false,
+            ),
+        ];
+
+        for (headers, expected) in cases {
+            let mut header_map = HeaderMap::new();
+            for (name, value) in headers {
+                header_map.insert(name, value.parse().unwrap());
+            }
+
+            let request = HtmxRequest::from_headers(&header_map);
+
+            assert_eq!(request.expects_fragment(), expected);
+        }
+    }
+
+    #[test]
+    fn response_sets_htmx_headers() {
+        let response = (
+            HtmxResponse::new()
+                .location(HtmxLocation::new("/path").target("#page"))
+                .trigger("first")
+                .trigger_with_detail("second", "detail")
+                .retarget("#target")
+                .reswap("innerHTML")
+                .push_url("/pushed")
+                .refresh(),
+            "",
+        )
+            .into_response();
+
+        let headers = response.headers();
+        assert_eq!(
+            headers.get("HX-Location").unwrap(),
+            r##"{"path":"/path","target":"#page"}"##
+        );
+        assert_eq!(
+            headers.get("HX-Trigger").unwrap(),
+            r#"{"first":null,"second":"detail"}"#
+        );
+        assert_eq!(headers.get("HX-Retarget").unwrap(), "#target");
+        assert_eq!(headers.get("HX-Reswap").unwrap(), "innerHTML");
+        assert_eq!(headers.get("HX-Push-Url").unwrap(), "/pushed");
+        assert_eq!(headers.get("HX-Refresh").unwrap(), "true");
+    }
+
+    #[test]
+    fn response_stores_out_of_band_swaps() {
+        let response = (
+            HtmxResponse::new().out_of_band_swap(r#"<div id="toast" hx-swap-oob="true"></div>"#),
+            "",
+        )
+            .into_response();
+
+        let OutOfBandSwaps(swaps) = response.extensions().get::<OutOfBandSwaps>().unwrap();
+        assert_eq!(swaps.len(), 1);
+    }
+
+    #[test]
+    fn only_html_content_is_swapped() {
+        let html = [(CONTENT_TYPE, "text/html; charset=utf-8")];
+
+        assert!(is_swapped_response(&(html, "").into_response()));
+        assert!(!is_swapped_response(&"".into_response()));
+        assert!(!is_swapped_response(
+            &(StatusCode::NO_CONTENT, html).into_response()
+        ));
+        assert!(!is_swapped_response(
+            &(HtmxResponse::new().refresh(), html, "").into_response()
+        ));
+    }
+}
@@ -1,23 +1,138 @@
 use crate::{
-    db::connection::{Database, SessionStore},
-    libs::auth::Backend,
+    api::htmx::HtmxRequest,
+    config::CookieConfig,
+    db::connection::SessionStore,
+    libs::{
+        auth::Backend,
+        secret_key::{CookieProtection, SecretKeys},
+    },
+};
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{HeaderMap, HeaderName},
+    response::Response,
 };
 use axum_login::{AuthManagerLayer, AuthManagerLayerBuilder};
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use std::time::Duration as LatencyDuration;
 use time::Duration;
+use tower::util::Either;
+use tower_http::{
+    classify::{ServerErrorsAsFailures, SharedClassifier},
+    trace::{DefaultOnRequest, MakeSpan, OnResponse, TraceLayer},
+};
 use tower_sessions::{
-    cookie::Key, cookie::SameSite, service::SignedCookie, Expiry, SessionManagerLayer,
+    cookie::SameSite,
+    service::{PrivateCookie, SignedCookie},
+    Expiry, SessionManagerLayer,
 };
+use tracing::{field::Empty, info, info_span, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+pub type AuthLayer = Either<
+    AuthManagerLayer<Backend, SessionStore, SignedCookie>,
+    AuthManagerLayer<Backend, SessionStore, PrivateCookie>,
+>;
 
 pub fn create_auth_layer(
     session_store: SessionStore,
-    db: Database,
-    secret_key: &[u8],
+    backend: Backend,
+    cookie: &CookieConfig,
+    secret_keys: &SecretKeys,
     expiration: Duration,
-) -> AuthManagerLayer<Backend, SessionStore, SignedCookie> {
-    let session_layer = SessionManagerLayer::new(session_store)
+) -> AuthLayer {
+    let mut session_layer = SessionManagerLayer::new(session_store)
+        .with_name(cookie.name.clone())
+        .with_path(cookie.path.clone())
+        .with_secure(cookie.secure)
         .with_expiry(Expiry::OnInactivity(expiration))
-        .with_signed(Key::from(secret_key))
         .with_same_site(SameSite::Lax);
-    let backend = Backend::new(db);
-    AuthManagerLayerBuilder::new(backend, session_layer).build()
+    if let Some(domain) = &cookie.domain {
+        session_layer = session_layer.with_domain(domain.clone());
+    }
+    let key = secret_keys.signing_key().clone();
+    match cookie.protection {
+        CookieProtection::Signed => Either::Left(
+            AuthManagerLayerBuilder::new(backend, session_layer.with_signed(key)).build(),
+        ),
+        CookieProtection::Private => Either::Right(
+            AuthManagerLayerBuilder::new(backend, session_layer.with_private(key)).build(),
+        ),
+    }
+}
+
+pub fn create_trace_layer() -> TraceLayer<
+    SharedClassifier<ServerErrorsAsFailures>,
+    MakeRequestSpan,
+    DefaultOnRequest,
+    RecordResponse,
+> {
+    TraceLayer::new_for_http()
+        .make_span_with(MakeRequestSpan)
+        .on_response(RecordResponse)
+}
+
+#[derive(Clone)]
+pub struct MakeRequestSpan;
+
+impl<B> MakeSpan<B> for MakeRequestSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let request_id = request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(MatchedPath::as_str)
+            .unwrap_or_default();
+        // Tells which element sent an htmx request, e.g. to debug a swap
+        let htmx_request = HtmxRequest::from_headers(request.headers());
+        // The remaining fields are recorded once known, see `RecordResponse`
+        // and the `record_user_id` middleware.
+        let span = info_span!(
+            "request",
+            request_id,
+            method = %request.method(),
+            route,
+            htmx_target = htmx_request.target.as_deref(),
+            htmx_trigger = htmx_request.trigger.as_deref(),
+            htmx_trigger_name = htmx_request.trigger_name.as_deref(),
+            status = Empty,
+            latency_ms = Empty,
+            user_id = Empty,
+        );
+        // Continue the trace started by the caller (W3C `traceparent` header)
+        let parent_context =
+            TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+        span.set_parent(parent_context);
+        span
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+#[derive(Clone)]
+pub struct RecordResponse;
+
+impl<B> OnResponse<B> for RecordResponse {
+    fn on_response(self, response: &Response<B>, latency: LatencyDuration, span: &Span) {
+        span.record("status", response.status().as_u16());
+        span.record("latency_ms", latency.as_millis() as u64);
+        info!("Finished processing request");
+    }
 }
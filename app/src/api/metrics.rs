@@ -0,0 +1,14 @@
+use crate::{metrics::record_db_pool_stats, state::MetricsState};
+use axum::{extract::State, routing::get, Router};
+
+pub fn create_metrics_router(state: MetricsState) -> Router {
+    Router::new()
+        .route("/metrics", get(get_metrics))
+        .with_state(state)
+}
+
+async fn get_metrics(State(state): State<MetricsState>) -> String {
+    // Pool stats are gauges, so it's enough to refresh them on scrape
+    record_db_pool_stats(&state.db);
+    state.metrics.render()
+}
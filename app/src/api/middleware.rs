@@ -1,19 +1,277 @@
-use axum::{extract::Request, http::header::CACHE_CONTROL, response::Response};
+use crate::{
+    api::{
+        constant::SESSION_EXPIRED_MESSAGE,
+        htmx::{is_swapped_response, HtmxRequest, HtmxResponse, OutOfBandSwaps},
+        layer::REQUEST_ID_HEADER,
+    },
+    libs::{
+        auth::AuthSession,
+        flash::{
+            push_flash_message, read_flash_messages, remove_flash_messages, FlashLevel,
+            FlashMessage, PendingFlashMessages,
+        },
+        session_lifetime::{read_session_lifetime, start_session_lifetime, SessionPolicies},
+    },
+    metrics::record_http_request,
+    state::SessionCookieState,
+};
+use askama_axum::Template;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{MatchedPath, Request, State},
+    http::{
+        header::{CACHE_CONTROL, CONTENT_LENGTH, COOKIE},
+        HeaderValue, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::time::Instant;
+use time::OffsetDateTime;
+use tower_sessions::{Expiry, Session};
+use tracing::{debug, error, Span};
+
+const MAX_REQUEST_ID_LENGTH: usize = 128;
 
 #[derive(Clone)]
 pub struct RenderOptions {
     pub use_base_layout: bool,
+    pub flash_messages: PendingFlashMessages,
+}
+
+// Incoming request IDs end up in logs, so anything unexpected is replaced
+// with a generated one.
+pub async fn discard_invalid_request_id<B>(mut request: Request<B>) -> Request<B> {
+    let is_valid = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(is_valid_request_id);
+    if is_valid == Some(false) {
+        request.headers_mut().remove(REQUEST_ID_HEADER);
+    }
+    request
+}
+
+fn is_valid_request_id(request_id: &str) -> bool {
+    !request_id.is_empty()
+        && request_id.len() <= MAX_REQUEST_ID_LENGTH
+        && request_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+pub async fn record_http_metrics(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    // Unmatched paths are grouped together to keep the number of series low
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(String::from("unmatched"), |path| path.as_str().to_owned());
+    let start = Instant::now();
+    let response = next.run(request).await;
+    record_http_request(&method, &route, response.status().as_u16(), start.elapsed());
+    response
+}
+
+#[derive(Clone)]
+struct ResignedSessionCookie;
+
+// Sessions signed with one of the previous secret keys are moved to the
+// current key, so they survive the previous key being removed.
+pub async fn resign_session_cookie(
+    State(session_cookie): State<SessionCookieState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let cookie_header = request
+        .headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join("; ");
+    let resigned_header = session_cookie
+        .secret_keys
+        .resign_cookie(
+            &cookie_header,
+            &session_cookie.name,
+            session_cookie.protection,
+        )
+        .and_then(|header| HeaderValue::from_str(&header).ok());
+    if let Some(header) = resigned_header {
+        debug!("Re-signed the session cookie signed with a previous key");
+        request.headers_mut().insert(COOKIE, header);
+        request.extensions_mut().insert(ResignedSessionCookie);
+    }
+    next.run(request).await
+}
+
+// Marking the session as modified sends the re-signed cookie back
+pub async fn save_resigned_session(session: Session, request: Request, next: Next) -> Response {
+    if request
+        .extensions()
+        .get::<ResignedSessionCookie>()
+        .is_some()
+    {
+        session.set_expiry(session.expiry());
+    }
+    next.run(request).await
+}
+
+// Signs out sessions past their absolute lifetime and moves the expiry of the
+// remaining ones, so the cookie and the stored session expire together.
+pub async fn enforce_session_lifetime(
+    State(policies): State<SessionPolicies>,
+    session: Session,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let htmx_request = HtmxRequest::from_headers(request.headers());
+    if let Some(auth_session) = request.extensions_mut().get_mut::<AuthSession>() {
+        if auth_session.user.is_some() {
+            match read_session_lifetime(&session).await {
+                Ok(Some(lifetime))
+                    if lifetime
+                        .expires_at(&policies, OffsetDateTime::now_utc())
+                        .is_none() =>
+                {
+                    debug!("Session exceeded its absolute lifetime");
+                    sign_out_expired_session(auth_session, &session).await;
+                    // The page still shows the signed in user, so it's
+                    // reloaded instead of swapping a fragment into it
+                    if htmx_request.expects_fragment() {
+                        return (StatusCode::OK, HtmxResponse::new().refresh()).into_response();
+                    }
+                }
+                // Sessions started before the lifetime was tracked
+                Ok(None) => {
+                    if let Err(e) = start_session_lifetime(&session, false).await {
+                        error!("Failed to start session lifetime: {:?}", e);
+                    }
+                }
+                Ok(Some(_)) => {}
+                Err(e) => error!("Failed to read session lifetime: {:?}", e),
+            }
+        }
+    }
+
+    let response = next.run(request).await;
+
+    match read_session_lifetime(&session).await {
+        Ok(Some(lifetime)) => {
+            if let Some(expires_at) = lifetime.expires_at(&policies, OffsetDateTime::now_utc()) {
+                session.set_expiry(Some(Expiry::AtDateTime(expires_at)));
+            }
+        }
+        Ok(None) => {}
+        Err(e) => error!("Failed to read session lifetime: {:?}", e),
+    }
+    response
+}
+
+async fn sign_out_expired_session(auth_session: &mut AuthSession, session: &Session) {
+    if let Err(e) = auth_session.logout().await {
+        error!("Failed to sign out expired session: {:?}", e);
+        return;
+    }
+    let message = FlashMessage {
+        level: FlashLevel::Info,
+        text: SESSION_EXPIRED_MESSAGE.to_owned(),
+    };
+    if let Err(e) = push_flash_message(session, message).await {
+        error!("Failed to push flash message: {:?}", e);
+    }
+}
+
+pub async fn record_user_id(auth_session: AuthSession, request: Request, next: Next) -> Response {
+    if let Some(user) = &auth_session.user {
+        Span::current().record("user_id", user.id);
+    }
+    next.run(request).await
 }
 
 pub async fn set_request_render_options<B>(mut request: Request<B>) -> Request<B> {
-    let is_htmx_request = request.headers().contains_key("HX-Request");
+    let htmx_request = HtmxRequest::from_headers(request.headers());
+    let flash_messages = request
+        .extensions()
+        .get::<PendingFlashMessages>()
+        .cloned()
+        .unwrap_or_default();
     let request_info = RenderOptions {
-        use_base_layout: !is_htmx_request,
+        use_base_layout: !htmx_request.expects_fragment(),
+        flash_messages,
     };
     request.extensions_mut().insert(request_info);
     request
 }
 
+#[derive(Template)]
+#[template(path = "components/out-of-band-toasts.html")]
+struct OutOfBandToastsTemplate {
+    flash_messages: PendingFlashMessages,
+}
+
+pub async fn deliver_flash_messages(
+    session: Session,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let htmx_request = HtmxRequest::from_headers(request.headers());
+    let messages = read_flash_messages(&session).await.unwrap_or_else(|e| {
+        error!("Failed to read flash messages: {:?}", e);
+        Vec::new()
+    });
+    let flash_messages = PendingFlashMessages::new(messages);
+    request.extensions_mut().insert(flash_messages.clone());
+
+    let mut response = next.run(request).await;
+
+    // Fragments rendered without the layout (e.g. polled statuses) carry the
+    // messages as an out-of-band swap instead
+    if htmx_request.expects_fragment()
+        && !flash_messages.is_delivered()
+        && !flash_messages.is_empty()
+        && is_swapped_response(&response)
+    {
+        let toasts = OutOfBandToastsTemplate {
+            flash_messages: flash_messages.clone(),
+        };
+        match toasts.render() {
+            Ok(toasts) => {
+                response = (HtmxResponse::new().out_of_band_swap(toasts), response).into_response()
+            }
+            Err(e) => error!("Failed to render flash messages: {:?}", e),
+        }
+    }
+
+    if flash_messages.is_delivered() && !flash_messages.is_empty() {
+        if let Err(e) = remove_flash_messages(&session, flash_messages.len()).await {
+            error!("Failed to remove delivered flash messages: {:?}", e);
+        }
+    }
+    response
+}
+
+pub async fn append_out_of_band_swaps(mut response: Response) -> Response {
+    let Some(OutOfBandSwaps(swaps)) = response.extensions_mut().remove::<OutOfBandSwaps>() else {
+        return response;
+    };
+    let (mut parts, body) = response.into_parts();
+    let mut content = match to_bytes(body, usize::MAX).await {
+        Err(e) => {
+            error!("Failed to read the response body: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+        Ok(bytes) => bytes.to_vec(),
+    };
+    for swap in swaps {
+        content.extend_from_slice(swap.as_bytes());
+    }
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(content))
+}
+
 pub async fn set_default_response_headers<B>(mut response: Response<B>) -> Response<B> {
     if response.status().is_success() && !response.headers().contains_key(CACHE_CONTROL.as_str()) {
         response.headers_mut().insert(
@@ -35,3 +293,20 @@ pub async fn set_default_response_headers_for_protected<B>(
     }
     response
 }
+
+#[cfg(test)]
+mod tests {
+    use super::is_valid_request_id;
+
+    #[test]
+    fn validate_request_id() {
+        let too_long_request_id = "a".repeat(129);
+
+        assert!(is_valid_request_id("4f1c2b7e-0d8a-4c8e-9a51-6b1f3e2d7c90"));
+        assert!(is_valid_request_id("req_123.abc"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("id with spaces"));
+        assert!(!is_valid_request_id("id\nwith\nnew\nlines"));
+        assert!(!is_valid_request_id(&too_long_request_id));
+    }
+}
@@ -1,25 +1,33 @@
-use crate::api::constant::PROTECTED_ROUTE;
+use crate::api::{
+    constant::{PAGE_CONTENT_SELECTOR, PROTECTED_ROUTE},
+    htmx::{HtmxLocation, HtmxResponse},
+    middleware::RenderOptions,
+};
 use axum::{
     http::StatusCode,
-    response::{IntoResponse, Redirect},
+    response::{IntoResponse, Redirect, Response},
 };
 
-const PAGE_CONTENT_SELECTOR: &str = "#page";
-
 pub fn create_client_side_redirect(status_code: StatusCode, path: &str) -> impl IntoResponse {
     (
         status_code,
-        [(
-            "HX-Location",
-            format!(
-                r#"{{"path":"{}","target":"{}"}}"#,
-                path, PAGE_CONTENT_SELECTOR
-            ),
-        )],
+        HtmxResponse::new().location(HtmxLocation::new(path).target(PAGE_CONTENT_SELECTOR)),
     )
         .into_response()
 }
 
+pub fn create_redirect_after_submission(
+    options: &RenderOptions,
+    status_code: StatusCode,
+    path: &str,
+) -> Response {
+    if options.use_base_layout {
+        // Plain form submission (e.g. htmx failed to load), so follow the Post/Redirect/Get pattern
+        return Redirect::to(path).into_response();
+    }
+    create_client_side_redirect(status_code, path).into_response()
+}
+
 pub fn create_redirect_for_authenticated() -> Redirect {
     Redirect::temporary(PROTECTED_ROUTE)
 }
@@ -1,7 +1,9 @@
 use crate::{
     api::app::{
+        account::create_account_router,
         asset::create_assets_router,
         auth::create_auth_router,
+        health::create_health_router,
         main::{create_main_router, handler_404},
         protected::create_protected_router,
     },
@@ -14,6 +16,8 @@ pub fn create_api_router() -> Router<AppState> {
         .merge(create_main_router())
         .merge(create_auth_router())
         .merge(create_protected_router())
+        .merge(create_account_router())
         .merge(create_assets_router())
+        .merge(create_health_router())
         .fallback(handler_404)
 }
@@ -0,0 +1,58 @@
+use crate::config::ConfigSources;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Path to a TOML config file, env variables take precedence over it
+    #[arg(long, short, env = "CONFIG_FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Print the effective configuration with secrets redacted and exit
+    #[arg(long)]
+    pub print_config: bool,
+
+    /// IP address to bind the server to
+    #[arg(long)]
+    pub host: Option<String>,
+
+    /// Port to bind the server to
+    #[arg(long)]
+    pub port: Option<String>,
+
+    /// Override any setting, e.g. `--set LOGGING_FILTER=debug`
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Generate a new secret key, put it in front of AUTH_SECRET_KEYS to rotate keys
+    GenerateKey,
+    /// Import users with pre-hashed passwords from a .csv or .json file
+    ImportUsers {
+        /// File with `email` and `password` (argon2, bcrypt, scrypt or pbkdf2-sha256 hash) fields
+        path: PathBuf,
+    },
+}
+
+impl Cli {
+    pub fn config_sources(&self) -> ConfigSources {
+        let mut overrides = Vec::new();
+        if let Some(host) = &self.host {
+            overrides.push(format!("SERVER_HOST={}", host));
+        }
+        if let Some(port) = &self.port {
+            overrides.push(format!("SERVER_PORT={}", port));
+        }
+        overrides.extend(self.overrides.iter().cloned());
+        ConfigSources {
+            file: self.config.as_deref(),
+            overrides,
+        }
+    }
+}
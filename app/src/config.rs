@@ -1,40 +1,381 @@
+mod source;
+
+use crate::libs::{
+    admin_token::MIN_ADMIN_TOKEN_LENGTH,
+    password::{PasswordHashing, Pepper, MIN_PEPPER_LENGTH},
+    password_policy::{PasswordPolicy, MAX_SCORE},
+    password_rotation::PasswordRotationPolicy,
+    secret_key::{CookieProtection, MIN_SECRET_KEY_LENGTH},
+};
+use argon2::{Algorithm, Params};
 use base64::{engine::general_purpose::STANDARD, Engine as _};
-use std::env::var;
+use source::{load_defaults, load_env, load_overrides, load_toml_file, ConfigValues, Setting};
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FormatResult},
+    fs::read_dir,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    str::FromStr,
+};
+
+// Every setting has to be listed here, unknown keys in the config file and
+// overrides are reported as errors to catch typos early.
+const SETTINGS: &[Setting] = &[
+    setting("ACCOUNT_DATA_EXPORT_EXPIRY_HOURS", Some("24")),
+    setting("ACCOUNT_DELETION_GRACE_PERIOD_DAYS", Some("30")),
+    setting("ACCOUNT_PURGE_DELETED_INTERVAL_SECONDS", Some("3600")),
+    setting("ADMIN_ADDRESS", None),
+    secret("ADMIN_TOKEN"),
+    setting("APP_ENVIRONMENT", Some("development")),
+    setting("AUTH_COOKIE_NAME", Some("id")),
+    setting("AUTH_COOKIE_DOMAIN", None),
+    setting("AUTH_COOKIE_PATH", Some("/")),
+    setting("AUTH_COOKIE_SECURE", Some("true")),
+    setting("AUTH_COOKIE_HOST_PREFIX", Some("false")),
+    setting("AUTH_COOKIE_PROTECTION", Some("signed")),
+    renamed(secret("AUTH_SECRET_KEYS"), "AUTH_SECRET_KEY"),
+    setting("AUTH_SESSION_IDLE_TIMEOUT_MINUTES", Some("30")),
+    setting("AUTH_SESSION_ABSOLUTE_LIFETIME_MINUTES", Some("720")),
+    setting("AUTH_REMEMBER_ME_IDLE_TIMEOUT_MINUTES", Some("10080")),
+    setting("AUTH_REMEMBER_ME_ABSOLUTE_LIFETIME_MINUTES", Some("43200")),
+    setting(
+        "AUTH_DELETE_EXPIRED_SESSIONS_INTERVAL_SECONDS",
+        Some("3600"),
+    ),
+    secret("DATABASE_URL"),
+    setting("DATABASE_POOL_MAX_CONNECTIONS", Some("5")),
+    renamed(setting("LOGGING_FILTER", Some("info")), "LOGGING_LEVEL"),
+    setting("LOGGING_FORMAT", Some("compact")),
+    setting("LOGGING_FILE_DIRECTORY", None),
+    setting("LOGGING_FILE_PREFIX", Some("app.log")),
+    setting("LOGGING_FILE_ROTATION", Some("daily")),
+    setting("OTEL_EXPORTER_OTLP_ENDPOINT", None),
+    setting("OTEL_SERVICE_NAME", Some("app")),
+    setting("PASSWORD_HASH_ALGORITHM", Some("argon2id")),
+    setting("PASSWORD_HASH_MEMORY_KIB", Some("19456")),
+    setting("PASSWORD_HASH_ITERATIONS", Some("2")),
+    setting("PASSWORD_HASH_PARALLELISM", Some("1")),
+    setting("PASSWORD_HASH_MAX_CONCURRENCY", Some("4")),
+    setting("PASSWORD_HASH_MAX_QUEUE_LENGTH", Some("64")),
+    secret("PASSWORD_PEPPERS"),
+    setting("PASSWORD_POLICY_MIN_SCORE", Some("3")),
+    setting("PASSWORD_POLICY_REJECT_COMMON", Some("true")),
+    setting("PASSWORD_POLICY_REJECT_EMAIL", Some("true")),
+    setting("PASSWORD_BREACHED_RANGES_DIRECTORY", None),
+    setting("PASSWORD_BREACHED_CHECK_FAIL_OPEN", Some("false")),
+    setting("PASSWORD_HISTORY_SIZE", Some("0")),
+    setting("PASSWORD_MAX_AGE_DAYS", Some("0")),
+    setting("SERVER_HOST", Some("0.0.0.0")),
+    setting("SERVER_PORT", Some("3000")),
+    setting("SERVER_SHUTDOWN_READINESS_DELAY_SECONDS", Some("0")),
+];
+
+const fn setting(key: &'static str, default: Option<&'static str>) -> Setting {
+    Setting {
+        key,
+        default,
+        secret: false,
+        deprecated_key: None,
+    }
+}
+
+const fn secret(key: &'static str) -> Setting {
+    Setting {
+        key,
+        default: None,
+        secret: true,
+        deprecated_key: None,
+    }
+}
+
+const fn renamed(setting: Setting, deprecated_key: &'static str) -> Setting {
+    Setting {
+        deprecated_key: Some(deprecated_key),
+        ..setting
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError(Vec<String>);
+
+impl Error for ConfigError {}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        write!(f, "Invalid configuration:")?;
+        for error in &self.0 {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+// Sources passed from the command line, the config file is loaded before the
+// env variables and the overrides after them.
+#[derive(Default)]
+pub struct ConfigSources<'a> {
+    pub file: Option<&'a Path>,
+    pub overrides: Vec<String>,
+}
+
+pub struct LoadedConfig {
+    values: ConfigValues,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl LoadedConfig {
+    pub fn load(sources: ConfigSources) -> Self {
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+        let mut values = load_defaults(SETTINGS);
+        if let Some(file) = sources.file {
+            values.merge(load_toml_file(file, SETTINGS, &mut errors, &mut warnings));
+        }
+        values.merge(load_env(SETTINGS, &mut errors, &mut warnings));
+        values.merge(load_overrides(
+            &sources.overrides,
+            SETTINGS,
+            &mut errors,
+            &mut warnings,
+        ));
+        Self {
+            values,
+            errors,
+            warnings,
+        }
+    }
+
+    // Secrets are redacted, so the output can be safely shared
+    pub fn to_redacted_string(&self) -> String {
+        self.values.to_redacted_string(SETTINGS)
+    }
+
+    pub fn validate(self) -> Result<Config, ConfigError> {
+        let mut reader = ConfigReader {
+            values: &self.values,
+            errors: self.errors,
+        };
+        let mut config = Config::read(&mut reader);
+        config.warnings = self.warnings;
+        if reader.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(reader.errors))
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Environment {
+    Development,
+    Production,
+}
 
 pub struct Config {
+    pub environment: Environment,
+    pub account: AccountConfig,
+    pub admin: AdminConfig,
     pub auth: AuthConfig,
     pub db: DatabaseConfig,
     pub logging: LoggingConfig,
+    pub password: PasswordConfig,
+    pub server: ServerConfig,
+    pub telemetry: TelemetryConfig,
+    // Problems which don't prevent starting, logged once logging is set up
+    pub warnings: Vec<String>,
 }
 
 impl Config {
+    pub fn load(sources: ConfigSources) -> Result<Self, ConfigError> {
+        LoadedConfig::load(sources).validate()
+    }
+
     pub fn from_env() -> Self {
+        Self::load(ConfigSources::default()).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn read(reader: &mut ConfigReader) -> Self {
+        let environment = reader
+            .one_of(
+                "APP_ENVIRONMENT",
+                &[
+                    ("development", Environment::Development),
+                    ("production", Environment::Production),
+                ],
+            )
+            .unwrap_or(Environment::Development);
+        let auth = AuthConfig::read(reader);
+        auth.cookie.validate(environment, reader);
         Self {
-            auth: AuthConfig::from_env(),
-            db: DatabaseConfig::from_env(),
-            logging: LoggingConfig::from_env(),
+            environment,
+            account: AccountConfig::read(reader),
+            admin: AdminConfig::read(reader),
+            auth,
+            db: DatabaseConfig::read(reader),
+            logging: LoggingConfig::read(reader),
+            password: PasswordConfig::read(reader),
+            server: ServerConfig::read(reader),
+            telemetry: TelemetryConfig::read(reader),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+pub struct AccountConfig {
+    // The download link and the export itself expire together
+    pub data_export_expiry_hours: u16,
+    // Deleted accounts can be restored by signing in until it ends
+    pub deletion_grace_period_days: u16,
+    pub purge_deleted_interval_seconds: u64,
+}
+
+impl AccountConfig {
+    fn read(reader: &mut ConfigReader) -> Self {
+        Self {
+            data_export_expiry_hours: reader.parse("ACCOUNT_DATA_EXPORT_EXPIRY_HOURS", "a number"),
+            deletion_grace_period_days: reader
+                .parse("ACCOUNT_DELETION_GRACE_PERIOD_DAYS", "a number"),
+            purge_deleted_interval_seconds: reader
+                .parse("ACCOUNT_PURGE_DELETED_INTERVAL_SECONDS", "a number"),
+        }
+    }
+}
+
+pub struct AdminConfig {
+    // The admin server is started only when the address is set, it should
+    // never be reachable from the public network. Metrics are served by the
+    // app server otherwise.
+    pub address: Option<SocketAddr>,
+    // Required by the endpoints which change users, they are disabled without it
+    pub token: Option<String>,
+}
+
+impl AdminConfig {
+    fn read(reader: &mut ConfigReader) -> Self {
+        let token = reader.optional("ADMIN_TOKEN");
+        reader.check(
+            token
+                .as_ref()
+                .map_or(true, |token| token.len() >= MIN_ADMIN_TOKEN_LENGTH),
+            &format!(
+                "ADMIN_TOKEN must be at least {} characters long",
+                MIN_ADMIN_TOKEN_LENGTH
+            ),
+        );
+        Self {
+            address: reader.parse_optional("ADMIN_ADDRESS", "a socket address"),
+            token,
         }
     }
 }
 
 pub struct AuthConfig {
-    pub secret_key: Vec<u8>,
-    pub session_expiration_minutes: i64,
+    pub cookie: CookieConfig,
+    // The first key signs session cookies, all of them verify
+    pub secret_keys: Vec<Vec<u8>>,
+    pub session: SessionLifetimeConfig,
+    // Used instead of the session limits when "remember me" is checked
+    pub remember_me: SessionLifetimeConfig,
     pub delete_expired_sessions_interval_seconds: u64,
 }
 
 impl AuthConfig {
-    pub fn from_env() -> Self {
+    fn read(reader: &mut ConfigReader) -> Self {
         Self {
-            secret_key: decode_base64(&read_env("AUTH_SECRET_KEY")),
-            session_expiration_minutes: read_env("AUTH_SESSION_EXPIRATION_MINUTES")
-                .parse()
-                .expect("AUTH_SESSION_EXPIRATION_MINUTES must be a number"),
-            delete_expired_sessions_interval_seconds: read_env(
-                "AUTH_DELETE_EXPIRED_SESSIONS_INTERVAL_SECONDS",
-            )
-            .parse()
-            .expect("AUTH_DELETE_EXPIRED_SESSIONS_INTERVAL_SECONDS must be a number"),
+            cookie: CookieConfig::read(reader),
+            secret_keys: reader.secret_keys("AUTH_SECRET_KEYS"),
+            session: SessionLifetimeConfig::read(reader, "AUTH_SESSION"),
+            remember_me: SessionLifetimeConfig::read(reader, "AUTH_REMEMBER_ME"),
+            delete_expired_sessions_interval_seconds: reader
+                .parse("AUTH_DELETE_EXPIRED_SESSIONS_INTERVAL_SECONDS", "a number"),
+        }
+    }
+}
+
+pub struct SessionLifetimeConfig {
+    pub idle_timeout_minutes: i64,
+    pub absolute_lifetime_minutes: i64,
+}
+
+impl SessionLifetimeConfig {
+    fn read(reader: &mut ConfigReader, prefix: &str) -> Self {
+        let idle_timeout_key = format!("{}_IDLE_TIMEOUT_MINUTES", prefix);
+        let absolute_lifetime_key = format!("{}_ABSOLUTE_LIFETIME_MINUTES", prefix);
+        let config = Self {
+            idle_timeout_minutes: reader.parse(&idle_timeout_key, "a number"),
+            absolute_lifetime_minutes: reader.parse(&absolute_lifetime_key, "a number"),
+        };
+        reader.check(
+            config.idle_timeout_minutes > 0
+                && config.idle_timeout_minutes <= config.absolute_lifetime_minutes,
+            &format!(
+                "{} must be positive and not greater than {}",
+                idle_timeout_key, absolute_lifetime_key
+            ),
+        );
+        config
+    }
+}
+
+const HOST_PREFIX: &str = "__Host-";
+const SECURE_PREFIX: &str = "__Secure-";
+
+pub struct CookieConfig {
+    // Includes the `__Host-` prefix if enabled
+    pub name: String,
+    pub domain: Option<String>,
+    pub path: String,
+    pub secure: bool,
+    pub protection: CookieProtection,
+}
+
+impl CookieConfig {
+    fn read(reader: &mut ConfigReader) -> Self {
+        let name = reader.required("AUTH_COOKIE_NAME");
+        let host_prefix: bool = reader.parse("AUTH_COOKIE_HOST_PREFIX", "true or false");
+        Self {
+            name: if host_prefix {
+                format!("{}{}", HOST_PREFIX, name)
+            } else {
+                name
+            },
+            domain: reader.optional("AUTH_COOKIE_DOMAIN"),
+            path: reader.required("AUTH_COOKIE_PATH"),
+            secure: reader.parse("AUTH_COOKIE_SECURE", "true or false"),
+            protection: reader
+                .one_of(
+                    "AUTH_COOKIE_PROTECTION",
+                    &[
+                        ("signed", CookieProtection::Signed),
+                        ("private", CookieProtection::Private),
+                    ],
+                )
+                .unwrap_or(CookieProtection::Signed),
+        }
+    }
+
+    // Browsers silently drop prefixed cookies not meeting the requirements,
+    // which would look like sessions are randomly lost.
+    fn validate(&self, environment: Environment, reader: &mut ConfigReader) {
+        if self.name.starts_with(HOST_PREFIX) {
+            reader.check(
+                self.secure && self.path == "/" && self.domain.is_none(),
+                "AUTH_COOKIE_HOST_PREFIX requires AUTH_COOKIE_SECURE, AUTH_COOKIE_PATH=/ and no AUTH_COOKIE_DOMAIN",
+            );
+        }
+        if self.name.starts_with(SECURE_PREFIX) {
+            reader.check(
+                self.secure,
+                "AUTH_COOKIE_NAME with the __Secure- prefix requires AUTH_COOKIE_SECURE",
+            );
+        }
+        if environment == Environment::Production {
+            reader.check(
+                self.secure,
+                "AUTH_COOKIE_SECURE must be enabled in production",
+            );
         }
     }
 }
@@ -45,32 +386,471 @@ pub struct DatabaseConfig {
 }
 
 impl DatabaseConfig {
-    pub fn from_env() -> Self {
+    fn read(reader: &mut ConfigReader) -> Self {
         Self {
-            url: read_env("DATABASE_URL"),
-            pool_max_connections: read_env("DATABASE_POOL_MAX_CONNECTIONS")
-                .parse()
-                .expect("DATABASE_POOL_MAX_CONNECTIONS must be a number"),
+            url: reader.required("DATABASE_URL"),
+            pool_max_connections: reader.parse("DATABASE_POOL_MAX_CONNECTIONS", "a number"),
         }
     }
 }
 
+#[derive(Clone, Copy)]
+pub enum LogFormat {
+    Compact,
+    Json,
+}
+
+#[derive(Clone, Copy)]
+pub enum LogFileRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+pub struct LogFileConfig {
+    pub directory: String,
+    pub prefix: String,
+    pub rotation: LogFileRotation,
+}
+
 pub struct LoggingConfig {
-    pub level: String,
+    pub filter: String,
+    pub format: LogFormat,
+    pub file: Option<LogFileConfig>,
 }
 
 impl LoggingConfig {
-    pub fn from_env() -> Self {
+    fn read(reader: &mut ConfigReader) -> Self {
+        let format = reader.one_of(
+            "LOGGING_FORMAT",
+            &[("compact", LogFormat::Compact), ("json", LogFormat::Json)],
+        );
+        let file = reader
+            .optional("LOGGING_FILE_DIRECTORY")
+            .map(|directory| LogFileConfig {
+                directory,
+                prefix: reader.required("LOGGING_FILE_PREFIX"),
+                rotation: reader.one_of(
+                    "LOGGING_FILE_ROTATION",
+                    &[
+                        ("hourly", LogFileRotation::Hourly),
+                        ("daily", LogFileRotation::Daily),
+                        ("never", LogFileRotation::Never),
+                    ],
+                ),
+            });
+        Self {
+            filter: reader.required("LOGGING_FILTER"),
+            format: format.unwrap_or(LogFormat::Compact),
+            file,
+        }
+    }
+}
+
+pub struct PasswordConfig {
+    pub hashing: PasswordHashing,
+    pub policy: PasswordPolicy,
+    // Passwords aren't checked against breaches when not set
+    pub breached_ranges_directory: Option<String>,
+    // Accept passwords when the breach check fails instead of rejecting them
+    pub breached_check_fail_open: bool,
+    pub rotation: PasswordRotationPolicy,
+}
+
+impl PasswordConfig {
+    fn read(reader: &mut ConfigReader) -> Self {
+        let algorithm = reader
+            .one_of(
+                "PASSWORD_HASH_ALGORITHM",
+                &[
+                    ("argon2id", Algorithm::Argon2id),
+                    ("argon2i", Algorithm::Argon2i),
+                    ("argon2d", Algorithm::Argon2d),
+                ],
+            )
+            .unwrap_or_default();
+        let memory_kib = reader.parse("PASSWORD_HASH_MEMORY_KIB", "a number");
+        let iterations = reader.parse("PASSWORD_HASH_ITERATIONS", "a number");
+        let parallelism = reader.parse("PASSWORD_HASH_PARALLELISM", "a number");
+        let params = Params::new(memory_kib, iterations, parallelism, None).unwrap_or_else(|e| {
+            reader.errors.push(format!(
+                "PASSWORD_HASH_MEMORY_KIB, PASSWORD_HASH_ITERATIONS and PASSWORD_HASH_PARALLELISM are invalid: {}",
+                e
+            ));
+            Params::default()
+        });
+        let max_concurrency = reader.parse("PASSWORD_HASH_MAX_CONCURRENCY", "a number");
+        reader.check(
+            max_concurrency > 0,
+            "PASSWORD_HASH_MAX_CONCURRENCY must be greater than 0",
+        );
+        let max_queue_length = reader.parse("PASSWORD_HASH_MAX_QUEUE_LENGTH", "a number");
+        let peppers = reader.peppers("PASSWORD_PEPPERS");
+        let hashing = PasswordHashing::new(algorithm, params)
+            .with_limits(max_concurrency, max_queue_length)
+            .with_peppers(peppers)
+            .unwrap_or_else(|e| {
+                reader
+                    .errors
+                    .push(format!("PASSWORD_PEPPERS are invalid: {}", e));
+                PasswordHashing::default()
+            });
+        let min_score = reader.parse("PASSWORD_POLICY_MIN_SCORE", "a number");
+        reader.check(
+            min_score <= MAX_SCORE,
+            "PASSWORD_POLICY_MIN_SCORE must be between 0 and 4",
+        );
+        let policy = PasswordPolicy {
+            min_score,
+            reject_common: reader.parse("PASSWORD_POLICY_REJECT_COMMON", "true or false"),
+            reject_email: reader.parse("PASSWORD_POLICY_REJECT_EMAIL", "true or false"),
+        };
         Self {
-            level: read_env("LOGGING_LEVEL"),
+            hashing,
+            policy,
+            breached_ranges_directory: reader
+                .readable_directory("PASSWORD_BREACHED_RANGES_DIRECTORY"),
+            breached_check_fail_open: reader
+                .parse("PASSWORD_BREACHED_CHECK_FAIL_OPEN", "true or false"),
+            rotation: PasswordRotationPolicy {
+                history_size: reader.parse("PASSWORD_HISTORY_SIZE", "a number"),
+                max_age_days: reader.parse("PASSWORD_MAX_AGE_DAYS", "a number"),
+            },
         }
     }
 }
 
-fn read_env(key: &str) -> String {
-    var(key).expect(&format!("Failed to read the {} env variable", key))
+pub struct ServerConfig {
+    pub host: IpAddr,
+    pub port: u16,
+    // Time between reporting not ready and closing the listener, so the load
+    // balancer stops routing new traffic to the shutting down instance.
+    pub shutdown_readiness_delay_seconds: u64,
+}
+
+impl ServerConfig {
+    fn read(reader: &mut ConfigReader) -> Self {
+        Self {
+            host: reader
+                .parse_optional("SERVER_HOST", "an IP address")
+                .unwrap_or(IpAddr::from([0, 0, 0, 0])),
+            port: reader.parse("SERVER_PORT", "a port number"),
+            shutdown_readiness_delay_seconds: reader
+                .parse("SERVER_SHUTDOWN_READINESS_DELAY_SECONDS", "a number"),
+        }
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        SocketAddr::new(self.host, self.port)
+    }
 }
 
-fn decode_base64(string: &str) -> Vec<u8> {
-    STANDARD.decode(string).expect("Invalid base64 string")
+pub struct TelemetryConfig {
+    // Traces are exported only when the collector endpoint is set
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+impl TelemetryConfig {
+    fn read(reader: &mut ConfigReader) -> Self {
+        Self {
+            otlp_endpoint: reader.optional("OTEL_EXPORTER_OTLP_ENDPOINT"),
+            service_name: reader.required("OTEL_SERVICE_NAME"),
+        }
+    }
+}
+
+// Collects all the problems instead of stopping at the first one, invalid
+// values are replaced with defaults as the config is discarded anyway.
+struct ConfigReader<'a> {
+    values: &'a ConfigValues,
+    errors: Vec<String>,
+}
+
+impl ConfigReader<'_> {
+    fn optional(&self, key: &str) -> Option<String> {
+        self.values.get(key).map(str::to_owned)
+    }
+
+    fn required(&mut self, key: &str) -> String {
+        self.optional(key).unwrap_or_else(|| {
+            self.errors.push(format!("{} is required", key));
+            String::new()
+        })
+    }
+
+    fn parse<T: FromStr + Default>(&mut self, key: &str, expected: &str) -> T {
+        if self.values.get(key).is_none() {
+            self.errors.push(format!("{} is required", key));
+        }
+        self.parse_optional(key, expected).unwrap_or_default()
+    }
+
+    fn parse_optional<T: FromStr>(&mut self, key: &str, expected: &str) -> Option<T> {
+        let values = self.values;
+        let value = values.get(key)?;
+        value.parse().map_or_else(
+            |_| {
+                self.errors.push(format!("{} must be {}", key, expected));
+                None
+            },
+            Some,
+        )
+    }
+
+    fn one_of<T: Copy>(&mut self, key: &str, options: &[(&str, T)]) -> Option<T> {
+        let value = self.required(key);
+        let option = options.iter().find(|(name, _)| *name == value);
+        if option.is_none() && !value.is_empty() {
+            let names: Vec<_> = options.iter().map(|(name, _)| *name).collect();
+            self.errors
+                .push(format!("{} must be one of: {}", key, names.join(", ")));
+        }
+        option.map(|(_, option)| *option)
+    }
+
+    // The directory is only read on demand, so a wrong path would otherwise
+    // go unnoticed until the first lookup
+    fn readable_directory(&mut self, key: &str) -> Option<String> {
+        let directory = self.optional(key)?;
+        if read_dir(&directory).is_err() {
+            self.errors
+                .push(format!("{} must be a readable directory", key));
+        }
+        Some(directory)
+    }
+
+    fn check(&mut self, condition: bool, message: &str) {
+        if !condition {
+            self.errors.push(message.to_owned());
+        }
+    }
+
+    // Entries are `<id>:<base64 secret>`, ids are stored in the hashes and
+    // limited to 8 bytes by argon2.
+    fn peppers(&mut self, key: &str) -> Vec<Pepper> {
+        let mut peppers: Vec<Pepper> = Vec::new();
+        let Some(value) = self.optional(key) else {
+            return peppers;
+        };
+        for (index, entry) in value.split(',').map(str::trim).enumerate() {
+            let Some((id, secret)) = entry.split_once(':').filter(|(id, _)| !id.is_empty()) else {
+                self.errors.push(format!(
+                    "{} entry {} must be in the <id>:<base64 secret> format",
+                    key,
+                    index + 1
+                ));
+                continue;
+            };
+            let secret = match STANDARD.decode(secret) {
+                Ok(secret) if secret.len() >= MIN_PEPPER_LENGTH => secret,
+                Ok(_) => {
+                    self.errors.push(format!(
+                        "{} entry {} secret must be at least {} bytes long",
+                        key,
+                        index + 1,
+                        MIN_PEPPER_LENGTH
+                    ));
+                    continue;
+                }
+                Err(_) => {
+                    self.errors.push(format!(
+                        "{} entry {} secret must be a base64 string",
+                        key,
+                        index + 1
+                    ));
+                    continue;
+                }
+            };
+            match Pepper::new(id, secret) {
+                Ok(pepper) if peppers.iter().any(|other| other.id() == pepper.id()) => self
+                    .errors
+                    .push(format!("{} entry {} id is duplicated", key, index + 1)),
+                Ok(pepper) => peppers.push(pepper),
+                Err(_) => self.errors.push(format!(
+                    "{} entry {} id must be at most 8 bytes long",
+                    key,
+                    index + 1
+                )),
+            }
+        }
+        peppers
+    }
+
+    fn secret_keys(&mut self, key: &str) -> Vec<Vec<u8>> {
+        let value = self.required(key);
+        let mut secret_keys = Vec::new();
+        if value.is_empty() {
+            return secret_keys;
+        }
+        for (index, secret_key) in value.split(',').map(str::trim).enumerate() {
+            match STANDARD.decode(secret_key) {
+                Ok(secret_key) if secret_key.len() >= MIN_SECRET_KEY_LENGTH => {
+                    secret_keys.push(secret_key)
+                }
+                Ok(_) => self.errors.push(format!(
+                    "{} key {} must be at least {} bytes long",
+                    key,
+                    index + 1,
+                    MIN_SECRET_KEY_LENGTH
+                )),
+                Err(_) => {
+                    self.errors
+                        .push(format!("{} key {} must be a base64 string", key, index + 1))
+                }
+            }
+        }
+        secret_keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ConfigReader, ConfigValues, CookieConfig, Environment, LogFormat, LoggingConfig,
+        ServerConfig,
+    };
+    use tempfile::tempdir;
+
+    #[test]
+    fn report_all_invalid_values() {
+        let mut values = ConfigValues::default();
+        values.set("SERVER_HOST", "localhost");
+        values.set("SERVER_PORT", "70000");
+        let mut reader = ConfigReader {
+            values: &values,
+            errors: Vec::new(),
+        };
+
+        ServerConfig::read(&mut reader);
+
+        assert_eq!(
+            reader.errors,
+            vec![
+                "SERVER_HOST must be an IP address",
+                "SERVER_PORT must be a port number",
+                "SERVER_SHUTDOWN_READINESS_DELAY_SECONDS is required",
+            ]
+        );
+    }
+
+    #[test]
+    fn read_valid_values() {
+        let mut values = ConfigValues::default();
+        values.set("LOGGING_FILTER", "debug");
+        values.set("LOGGING_FORMAT", "json");
+        let mut reader = ConfigReader {
+            values: &values,
+            errors: Vec::new(),
+        };
+
+        let config = LoggingConfig::read(&mut reader);
+
+        assert!(reader.errors.is_empty());
+        assert_eq!(config.filter, "debug");
+        assert!(matches!(config.format, LogFormat::Json));
+        assert!(config.file.is_none());
+    }
+
+    #[test]
+    fn validate_secret_keys() {
+        let mut values = ConfigValues::default();
+        values.set(
+            "AUTH_SECRET_KEYS",
+            &format!("{}, c2hvcnQ=, not base64", "a".repeat(88)),
+        );
+        let mut reader = ConfigReader {
+            values: &values,
+            errors: Vec::new(),
+        };
+
+        let secret_keys = reader.secret_keys("AUTH_SECRET_KEYS");
+
+        assert_eq!(secret_keys.len(), 1);
+        assert_eq!(
+            reader.errors,
+            vec![
+                "AUTH_SECRET_KEYS key 2 must be at least 64 bytes long",
+                "AUTH_SECRET_KEYS key 3 must be a base64 string",
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_readable_directory() {
+        let directory = tempdir().unwrap();
+        let missing = directory.path().join("missing");
+        let mut values = ConfigValues::default();
+        values.set("EXISTING_DIRECTORY", &directory.path().to_string_lossy());
+        values.set("MISSING_DIRECTORY", &missing.to_string_lossy());
+        let mut reader = ConfigReader {
+            values: &values,
+            errors: Vec::new(),
+        };
+
+        assert!(reader.readable_directory("EXISTING_DIRECTORY").is_some());
+        assert!(reader.readable_directory("MISSING_DIRECTORY").is_some());
+        assert!(reader.readable_directory("UNSET_DIRECTORY").is_none());
+        assert_eq!(
+            reader.errors,
+            vec!["MISSING_DIRECTORY must be a readable directory"]
+        );
+    }
+
+    #[test]
+    fn validate_peppers() {
+        let secret = "a".repeat(44);
+        let mut values = ConfigValues::default();
+        values.set(
+            "PASSWORD_PEPPERS",
+            &format!(
+                "2:{secret}, 1:{secret}, 1:{secret}, {secret}, too-long-id:{secret}, 3:c2hvcnQ="
+            ),
+        );
+        let mut reader = ConfigReader {
+            values: &values,
+            errors: Vec::new(),
+        };
+
+        let peppers = reader.peppers("PASSWORD_PEPPERS");
+
+        assert_eq!(peppers.len(), 2);
+        assert_eq!(peppers[0].id(), b"2");
+        assert_eq!(
+            reader.errors,
+            vec![
+                "PASSWORD_PEPPERS entry 3 id is duplicated",
+                "PASSWORD_PEPPERS entry 4 must be in the <id>:<base64 secret> format",
+                "PASSWORD_PEPPERS entry 5 id must be at most 8 bytes long",
+                "PASSWORD_PEPPERS entry 6 secret must be at least 32 bytes long",
+            ]
+        );
+    }
+
+    #[test]
+    fn reject_insecure_cookie_policy() {
+        let mut values = ConfigValues::default();
+        values.set("AUTH_COOKIE_NAME", "id");
+        values.set("AUTH_COOKIE_DOMAIN", "example.com");
+        values.set("AUTH_COOKIE_PATH", "/");
+        values.set("AUTH_COOKIE_SECURE", "false");
+        values.set("AUTH_COOKIE_HOST_PREFIX", "true");
+        values.set("AUTH_COOKIE_PROTECTION", "private");
+        let mut reader = ConfigReader {
+            values: &values,
+            errors: Vec::new(),
+        };
+
+        let cookie = CookieConfig::read(&mut reader);
+        cookie.validate(Environment::Production, &mut reader);
+
+        assert_eq!(cookie.name, "__Host-id");
+        assert_eq!(
+            reader.errors,
+            vec![
+                "AUTH_COOKIE_HOST_PREFIX requires AUTH_COOKIE_SECURE, AUTH_COOKIE_PATH=/ and no AUTH_COOKIE_DOMAIN",
+                "AUTH_COOKIE_SECURE must be enabled in production",
+            ]
+        );
+    }
 }
@@ -0,0 +1,211 @@
+use crate::{
+    db::{
+        connection::Database,
+        data_export::{
+            build_user_data_export, complete_data_export, create_data_export, fail_data_export,
+            DataExportError,
+        },
+        session::{delete_user_sessions, get_user_session_ids},
+        user::{
+            delete_users_past_scheduled_deletion, schedule_user_deletion, DeleteUserError,
+            UpdateUserError,
+        },
+    },
+    libs::auth::{AuthError, AuthSession},
+    metrics::record_auth_event,
+};
+use sqlx::Error as SqlxError;
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FormatResult},
+};
+use tokio::task::spawn;
+use tracing::{error, instrument};
+
+pub const DATA_EXPORT_FILE_NAME: &str = "data-export.json";
+// Pending exports older than this are considered abandoned
+pub const DATA_EXPORT_TIMEOUT_MINUTES: i32 = 15;
+
+#[derive(Debug)]
+pub enum DeleteAccountError {
+    UserNotFoundError,
+    UpdateUserError(UpdateUserError),
+    LogoutError(AuthError),
+    DeleteSessionsError(SqlxError),
+}
+
+impl Error for DeleteAccountError {}
+
+impl Display for DeleteAccountError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        match self {
+            DeleteAccountError::UserNotFoundError => write!(f, "User not found"),
+            DeleteAccountError::UpdateUserError(e) => write!(f, "Update user error: {}", e),
+            DeleteAccountError::LogoutError(e) => write!(f, "Logout error: {}", e),
+            DeleteAccountError::DeleteSessionsError(e) => {
+                write!(f, "Delete sessions error: {}", e)
+            }
+        }
+    }
+}
+
+impl From<UpdateUserError> for DeleteAccountError {
+    fn from(value: UpdateUserError) -> Self {
+        DeleteAccountError::UpdateUserError(value)
+    }
+}
+
+impl From<AuthError> for DeleteAccountError {
+    fn from(value: AuthError) -> Self {
+        DeleteAccountError::LogoutError(value)
+    }
+}
+
+impl From<SqlxError> for DeleteAccountError {
+    fn from(value: SqlxError) -> Self {
+        DeleteAccountError::DeleteSessionsError(value)
+    }
+}
+
+// The account is only scheduled for deletion, signing in during the grace
+// period cancels it. All sessions of the user are signed out.
+#[instrument(skip_all)]
+pub async fn delete_account(
+    grace_period_days: u16,
+    db: &Database,
+    auth_session: &mut AuthSession,
+) -> Result<(), DeleteAccountError> {
+    let result = schedule_deletion_and_logout(grace_period_days, db, auth_session).await;
+    record_auth_event(
+        "delete_account",
+        if result.is_ok() { "success" } else { "error" },
+    );
+    result
+}
+
+async fn schedule_deletion_and_logout(
+    grace_period_days: u16,
+    db: &Database,
+    auth_session: &mut AuthSession,
+) -> Result<(), DeleteAccountError> {
+    let user_id = auth_session
+        .user
+        .as_ref()
+        .ok_or(DeleteAccountError::UserNotFoundError)?
+        .id;
+    if !schedule_user_deletion(user_id, grace_period_days.into(), db).await? {
+        return Err(DeleteAccountError::UserNotFoundError);
+    }
+    auth_session.logout().await?;
+    delete_user_sessions(&[user_id], db).await?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum PurgeAccountsError {
+    DeleteUserError(DeleteUserError),
+    DeleteSessionsError(SqlxError),
+}
+
+impl Error for PurgeAccountsError {}
+
+impl Display for PurgeAccountsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        match self {
+            PurgeAccountsError::DeleteUserError(e) => write!(f, "Delete user error: {}", e),
+            PurgeAccountsError::DeleteSessionsError(e) => {
+                write!(f, "Delete sessions error: {}", e)
+            }
+        }
+    }
+}
+
+impl From<DeleteUserError> for PurgeAccountsError {
+    fn from(value: DeleteUserError) -> Self {
+        PurgeAccountsError::DeleteUserError(value)
+    }
+}
+
+impl From<SqlxError> for PurgeAccountsError {
+    fn from(value: SqlxError) -> Self {
+        PurgeAccountsError::DeleteSessionsError(value)
+    }
+}
+
+// Removes accounts past their grace period together with any remaining
+// sessions, returns the number of purged accounts.
+#[instrument(skip_all)]
+pub async fn purge_deleted_accounts(db: &Database) -> Result<usize, PurgeAccountsError> {
+    let user_ids = delete_users_past_scheduled_deletion(db).await?;
+    if !user_ids.is_empty() {
+        delete_user_sessions(&user_ids, db).await?;
+    }
+    Ok(user_ids.len())
+}
+
+// Keeps the pending export when there is one already, otherwise starts a new
+// one. Exports are generated in the background, as the sign-in history of
+// long-standing accounts can get large.
+#[instrument(skip_all)]
+pub async fn request_data_export(
+    user_id: i32,
+    expiry_hours: u16,
+    db: &Database,
+) -> Result<(), DataExportError> {
+    let export = create_data_export(
+        user_id,
+        expiry_hours.into(),
+        DATA_EXPORT_TIMEOUT_MINUTES,
+        db,
+    )
+    .await?;
+    if let Some(export) = export {
+        spawn(generate_data_export(export.id, user_id, db.clone()));
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+enum GenerateDataExportError {
+    DataExportError(DataExportError),
+    GetSessionsError(SqlxError),
+}
+
+impl Display for GenerateDataExportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        match self {
+            GenerateDataExportError::DataExportError(e) => write!(f, "Data export error: {}", e),
+            GenerateDataExportError::GetSessionsError(e) => write!(f, "Get sessions error: {}", e),
+        }
+    }
+}
+
+impl From<DataExportError> for GenerateDataExportError {
+    fn from(value: DataExportError) -> Self {
+        GenerateDataExportError::DataExportError(value)
+    }
+}
+
+impl From<SqlxError> for GenerateDataExportError {
+    fn from(value: SqlxError) -> Self {
+        GenerateDataExportError::GetSessionsError(value)
+    }
+}
+
+async fn generate_data_export(export_id: i32, user_id: i32, db: Database) {
+    let result = match build_data_export(user_id, &db).await {
+        Ok(data) => complete_data_export(export_id, &data, &db).await,
+        Err(e) => {
+            error!("Failed to generate data export: {}", e);
+            fail_data_export(export_id, &db).await
+        }
+    };
+    if let Err(e) = result {
+        error!("Failed to save data export: {}", e);
+    }
+}
+
+async fn build_data_export(user_id: i32, db: &Database) -> Result<String, GenerateDataExportError> {
+    let session_ids = get_user_session_ids(&[user_id], db).await?;
+    Ok(build_user_data_export(user_id, &session_ids, db).await?)
+}
@@ -1,29 +1,62 @@
 use crate::{
     db::{
         connection::Database,
-        user::{create_user, CreateUserData, CreateUserError},
+        session::{track_user_session, TrackSessionError},
+        sign_in_event::record_sign_in_event,
+        user::{
+            cancel_user_deletion, change_user_password, create_user, get_password_history,
+            user_must_change_password, user_with_email_exists, CreateUserData, CreateUserError,
+            GetUserError, UpdateUserError,
+        },
     },
     libs::{
         auth::{AuthError, AuthSession, Credentials},
-        password::{hash_password_in_separate_thread, HashPasswordError},
+        password::{
+            hash_password_in_separate_thread, verify_password_in_separate_thread,
+            HashPasswordError, PasswordHashing, VerifyPasswordError,
+        },
+        password_rotation::{mark_password_change_required, PasswordRotationPolicy},
+        recent_auth::record_strong_auth,
+        redact::Redacted,
+        session_lifetime::start_session_lifetime,
     },
+    metrics::record_auth_event,
 };
 use std::{
     error::Error,
-    fmt::{Display, Formatter, Result as FormatResult},
+    fmt::{Debug as FormatDebug, Display, Formatter, Result as FormatResult},
 };
+use tower_sessions::session::Error as SessionError;
+use tracing::{error, instrument, warn};
 
 pub struct SignupData<'a> {
     pub email: &'a str,
     pub password: String,
 }
 
+impl FormatDebug for SignupData<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        f.debug_struct("SignupData")
+            .field("email", &Redacted(self.email))
+            .field("password", &Redacted(&self.password))
+            .finish()
+    }
+}
+
 #[derive(Debug)]
 pub enum SignupError {
     HashPasswordError(HashPasswordError),
     UserEmailAlreadyExistsError,
     CreateUserError(CreateUserError),
     LoginError(AuthError),
+    SessionError(SessionError),
+    TrackSessionError(TrackSessionError),
+}
+
+impl SignupError {
+    pub fn is_hashing_busy(&self) -> bool {
+        matches!(self, SignupError::HashPasswordError(e) if e.is_busy())
+    }
 }
 
 impl Error for SignupError {}
@@ -35,6 +68,8 @@ impl Display for SignupError {
             SignupError::UserEmailAlreadyExistsError => write!(f, "User email already exists"),
             SignupError::CreateUserError(e) => write!(f, "Create user error: {}", e),
             SignupError::LoginError(e) => write!(f, "Login error: {}", e),
+            SignupError::SessionError(e) => write!(f, "Session error: {}", e),
+            SignupError::TrackSessionError(e) => write!(f, "Track session error: {}", e),
         }
     }
 }
@@ -60,12 +95,43 @@ impl From<AuthError> for SignupError {
     }
 }
 
+impl From<SessionError> for SignupError {
+    fn from(value: SessionError) -> Self {
+        SignupError::SessionError(value)
+    }
+}
+
+impl From<TrackSessionError> for SignupError {
+    fn from(value: TrackSessionError) -> Self {
+        SignupError::TrackSessionError(value)
+    }
+}
+
+#[instrument(skip_all)]
 pub async fn sign_up(
     data: SignupData<'_>,
     db: &Database,
+    password_hashing: &PasswordHashing,
     auth_session: &mut AuthSession,
 ) -> Result<(), SignupError> {
-    let hashed_password = hash_password_in_separate_thread(data.password).await?;
+    let result = create_user_and_login(data, db, password_hashing, auth_session).await;
+    let outcome = match &result {
+        Ok(_) => "success",
+        Err(SignupError::UserEmailAlreadyExistsError) => "email_taken",
+        Err(e) if e.is_hashing_busy() => "busy",
+        Err(_) => "error",
+    };
+    record_auth_event("signup", outcome);
+    result
+}
+
+async fn create_user_and_login(
+    data: SignupData<'_>,
+    db: &Database,
+    password_hashing: &PasswordHashing,
+    auth_session: &mut AuthSession,
+) -> Result<(), SignupError> {
+    let hashed_password = hash_password_in_separate_thread(data.password, password_hashing).await?;
     let user = create_user(
         CreateUserData {
             email: data.email,
@@ -75,18 +141,53 @@ pub async fn sign_up(
     )
     .await?;
     auth_session.login(&user).await?;
+    start_session_lifetime(&auth_session.session, false).await?;
+    record_strong_auth(&auth_session.session).await?;
+    track_user_session(user.id, &auth_session.session, db).await?;
     Ok(())
 }
 
+#[instrument(skip_all)]
+pub async fn is_email_available(email: &str, db: &Database) -> Result<bool, GetUserError> {
+    let exists = user_with_email_exists(email, db).await?;
+    Ok(!exists)
+}
+
 pub struct SigninData {
     pub email: String,
     pub password: String,
+    pub remember_me: bool,
+    // Kept in the sign-in history
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl FormatDebug for SigninData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        f.debug_struct("SigninData")
+            .field("email", &Redacted(&self.email))
+            .field("password", &Redacted(&self.password))
+            .field("remember_me", &self.remember_me)
+            .field("ip_address", &self.ip_address.as_deref().map(Redacted))
+            .field("user_agent", &self.user_agent)
+            .finish()
+    }
 }
 
 #[derive(Debug)]
 pub enum SigninError {
     InvalidCredentialsError,
     AuthenticationError(AuthError),
+    GetUserError(GetUserError),
+    UpdateUserError(UpdateUserError),
+    SessionError(SessionError),
+    TrackSessionError(TrackSessionError),
+}
+
+impl SigninError {
+    pub fn is_hashing_busy(&self) -> bool {
+        matches!(self, SigninError::AuthenticationError(AuthError::Backend(e)) if e.is_hashing_busy())
+    }
 }
 
 impl Error for SigninError {}
@@ -96,6 +197,10 @@ impl Display for SigninError {
         match self {
             SigninError::InvalidCredentialsError => write!(f, "Invalid credentials"),
             SigninError::AuthenticationError(e) => write!(f, "Authentication error: {}", e),
+            SigninError::GetUserError(e) => write!(f, "Get user error: {}", e),
+            SigninError::UpdateUserError(e) => write!(f, "Update user error: {}", e),
+            SigninError::SessionError(e) => write!(f, "Session error: {}", e),
+            SigninError::TrackSessionError(e) => write!(f, "Track session error: {}", e),
         }
     }
 }
@@ -106,7 +211,61 @@ impl From<AuthError> for SigninError {
     }
 }
 
-pub async fn sign_in(data: SigninData, auth_session: &mut AuthSession) -> Result<(), SigninError> {
+impl From<GetUserError> for SigninError {
+    fn from(value: GetUserError) -> Self {
+        SigninError::GetUserError(value)
+    }
+}
+
+impl From<UpdateUserError> for SigninError {
+    fn from(value: UpdateUserError) -> Self {
+        SigninError::UpdateUserError(value)
+    }
+}
+
+impl From<SessionError> for SigninError {
+    fn from(value: SessionError) -> Self {
+        SigninError::SessionError(value)
+    }
+}
+
+impl From<TrackSessionError> for SigninError {
+    fn from(value: TrackSessionError) -> Self {
+        SigninError::TrackSessionError(value)
+    }
+}
+
+pub struct SigninOutcome {
+    // The user has to change their password before continuing
+    pub must_change_password: bool,
+    // The account was scheduled for deletion and signing in restored it
+    pub deletion_cancelled: bool,
+}
+
+#[instrument(skip_all)]
+pub async fn sign_in(
+    data: SigninData,
+    db: &Database,
+    rotation_policy: &PasswordRotationPolicy,
+    auth_session: &mut AuthSession,
+) -> Result<SigninOutcome, SigninError> {
+    let result = authenticate_and_login(data, db, rotation_policy, auth_session).await;
+    let outcome = match &result {
+        Ok(_) => "success",
+        Err(SigninError::InvalidCredentialsError) => "invalid_credentials",
+        Err(e) if e.is_hashing_busy() => "busy",
+        Err(_) => "error",
+    };
+    record_auth_event("signin", outcome);
+    result
+}
+
+async fn authenticate_and_login(
+    data: SigninData,
+    db: &Database,
+    rotation_policy: &PasswordRotationPolicy,
+    auth_session: &mut AuthSession,
+) -> Result<SigninOutcome, SigninError> {
     let user = auth_session
         .authenticate(Credentials {
             email: data.email,
@@ -114,8 +273,32 @@ pub async fn sign_in(data: SigninData, auth_session: &mut AuthSession) -> Result
         })
         .await?
         .ok_or(SigninError::InvalidCredentialsError)?;
+    // Before logging in, as sessions of users scheduled for deletion are
+    // invalid. The account may have been purged since it was authenticated.
+    let deletion_cancelled = cancel_user_deletion(user.id, db)
+        .await?
+        .ok_or(SigninError::InvalidCredentialsError)?;
     auth_session.login(&user).await?;
-    Ok(())
+    if let Err(e) = record_sign_in_event(
+        user.id,
+        data.ip_address.as_deref(),
+        data.user_agent.as_deref(),
+        db,
+    )
+    .await
+    {
+        error!("Failed to record sign-in event: {}", e);
+    }
+    start_session_lifetime(&auth_session.session, data.remember_me).await?;
+    record_strong_auth(&auth_session.session).await?;
+    let must_change_password =
+        user_must_change_password(user.id, rotation_policy.max_age_days.into(), db).await?;
+    mark_password_change_required(&auth_session.session, must_change_password).await?;
+    track_user_session(user.id, &auth_session.session, db).await?;
+    Ok(SigninOutcome {
+        must_change_password,
+        deletion_cancelled,
+    })
 }
 
 #[derive(Debug)]
@@ -135,7 +318,238 @@ impl From<AuthError> for SignoutError {
     }
 }
 
+#[instrument(skip_all)]
 pub async fn sign_out(auth_session: &mut AuthSession) -> Result<(), SignoutError> {
-    auth_session.logout().await?;
+    let result = auth_session.logout().await;
+    record_auth_event("signout", if result.is_ok() { "success" } else { "error" });
+    result?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ReauthenticateError {
+    InvalidPasswordError,
+    VerifyPasswordError(VerifyPasswordError),
+    SessionError(SessionError),
+}
+
+impl ReauthenticateError {
+    pub fn is_hashing_busy(&self) -> bool {
+        matches!(self, ReauthenticateError::VerifyPasswordError(e) if e.is_busy())
+    }
+}
+
+impl Error for ReauthenticateError {}
+
+impl Display for ReauthenticateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        match self {
+            ReauthenticateError::InvalidPasswordError => write!(f, "Invalid password"),
+            ReauthenticateError::VerifyPasswordError(e) => {
+                write!(f, "Verify password error: {}", e)
+            }
+            ReauthenticateError::SessionError(e) => write!(f, "Session error: {}", e),
+        }
+    }
+}
+
+impl From<VerifyPasswordError> for ReauthenticateError {
+    fn from(value: VerifyPasswordError) -> Self {
+        ReauthenticateError::VerifyPasswordError(value)
+    }
+}
+
+impl From<SessionError> for ReauthenticateError {
+    fn from(value: SessionError) -> Self {
+        ReauthenticateError::SessionError(value)
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn reauthenticate(
+    password: String,
+    password_hashing: &PasswordHashing,
+    auth_session: &AuthSession,
+) -> Result<(), ReauthenticateError> {
+    let result = verify_password_and_record(password, password_hashing, auth_session).await;
+    let outcome = match &result {
+        Ok(_) => "success",
+        Err(ReauthenticateError::InvalidPasswordError) => "invalid_credentials",
+        Err(e) if e.is_hashing_busy() => "busy",
+        Err(_) => "error",
+    };
+    record_auth_event("reauthenticate", outcome);
+    result
+}
+
+async fn verify_password_and_record(
+    password: String,
+    password_hashing: &PasswordHashing,
+    auth_session: &AuthSession,
+) -> Result<(), ReauthenticateError> {
+    let user = auth_session
+        .user
+        .as_ref()
+        .ok_or(ReauthenticateError::InvalidPasswordError)?;
+    if !verify_password_in_separate_thread(password, user.password.clone(), password_hashing)
+        .await?
+    {
+        return Err(ReauthenticateError::InvalidPasswordError);
+    }
+    record_strong_auth(&auth_session.session).await?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ChangePasswordError {
+    UserNotFoundError,
+    PasswordReusedError,
+    GetUserError(GetUserError),
+    VerifyPasswordError(VerifyPasswordError),
+    HashPasswordError(HashPasswordError),
+    UpdateUserError(UpdateUserError),
+    LoginError(AuthError),
+    SessionError(SessionError),
+    TrackSessionError(TrackSessionError),
+}
+
+impl ChangePasswordError {
+    pub fn is_hashing_busy(&self) -> bool {
+        match self {
+            ChangePasswordError::VerifyPasswordError(e) => e.is_busy(),
+            ChangePasswordError::HashPasswordError(e) => e.is_busy(),
+            _ => false,
+        }
+    }
+}
+
+impl Error for ChangePasswordError {}
+
+impl Display for ChangePasswordError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        match self {
+            ChangePasswordError::UserNotFoundError => write!(f, "User not found"),
+            ChangePasswordError::PasswordReusedError => write!(f, "Password was used before"),
+            ChangePasswordError::GetUserError(e) => write!(f, "Get user error: {}", e),
+            ChangePasswordError::VerifyPasswordError(e) => {
+                write!(f, "Verify password error: {}", e)
+            }
+            ChangePasswordError::HashPasswordError(e) => write!(f, "Hash password error: {}", e),
+            ChangePasswordError::UpdateUserError(e) => write!(f, "Update user error: {}", e),
+            ChangePasswordError::LoginError(e) => write!(f, "Login error: {}", e),
+            ChangePasswordError::SessionError(e) => write!(f, "Session error: {}", e),
+            ChangePasswordError::TrackSessionError(e) => write!(f, "Track session error: {}", e),
+        }
+    }
+}
+
+impl From<GetUserError> for ChangePasswordError {
+    fn from(value: GetUserError) -> Self {
+        ChangePasswordError::GetUserError(value)
+    }
+}
+
+impl From<VerifyPasswordError> for ChangePasswordError {
+    fn from(value: VerifyPasswordError) -> Self {
+        ChangePasswordError::VerifyPasswordError(value)
+    }
+}
+
+impl From<HashPasswordError> for ChangePasswordError {
+    fn from(value: HashPasswordError) -> Self {
+        ChangePasswordError::HashPasswordError(value)
+    }
+}
+
+impl From<UpdateUserError> for ChangePasswordError {
+    fn from(value: UpdateUserError) -> Self {
+        ChangePasswordError::UpdateUserError(value)
+    }
+}
+
+impl From<AuthError> for ChangePasswordError {
+    fn from(value: AuthError) -> Self {
+        ChangePasswordError::LoginError(value)
+    }
+}
+
+impl From<SessionError> for ChangePasswordError {
+    fn from(value: SessionError) -> Self {
+        ChangePasswordError::SessionError(value)
+    }
+}
+
+impl From<TrackSessionError> for ChangePasswordError {
+    fn from(value: TrackSessionError) -> Self {
+        ChangePasswordError::TrackSessionError(value)
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn change_password(
+    password: String,
+    db: &Database,
+    password_hashing: &PasswordHashing,
+    rotation_policy: &PasswordRotationPolicy,
+    auth_session: &mut AuthSession,
+) -> Result<(), ChangePasswordError> {
+    let result = check_history_and_change_password(
+        password,
+        db,
+        password_hashing,
+        rotation_policy,
+        auth_session,
+    )
+    .await;
+    let outcome = match &result {
+        Ok(_) => "success",
+        Err(ChangePasswordError::PasswordReusedError) => "password_reused",
+        Err(e) if e.is_hashing_busy() => "busy",
+        Err(_) => "error",
+    };
+    record_auth_event("change_password", outcome);
+    result
+}
+
+async fn check_history_and_change_password(
+    password: String,
+    db: &Database,
+    password_hashing: &PasswordHashing,
+    rotation_policy: &PasswordRotationPolicy,
+    auth_session: &mut AuthSession,
+) -> Result<(), ChangePasswordError> {
+    let user_id = auth_session
+        .user
+        .as_ref()
+        .ok_or(ChangePasswordError::UserNotFoundError)?
+        .id;
+    // The history starts with the current password, which is always rejected
+    let history_size = i64::from(rotation_policy.history_size) + 1;
+    for hashed_password in get_password_history(user_id, history_size, db).await? {
+        match verify_password_in_separate_thread(
+            password.clone(),
+            hashed_password,
+            password_hashing,
+        )
+        .await
+        {
+            Ok(true) => return Err(ChangePasswordError::PasswordReusedError),
+            Ok(false) => {}
+            Err(e) if e.is_busy() => return Err(e.into()),
+            // E.g. a malformed hash, it can't be compared anymore
+            Err(e) => warn!("Skipping password history entry: {}", e),
+        }
+    }
+    let hashed_password = hash_password_in_separate_thread(password, password_hashing).await?;
+    let user = change_user_password(user_id, &hashed_password, db)
+        .await?
+        .ok_or(ChangePasswordError::UserNotFoundError)?;
+    // Sessions are tied to the session version which is bumped with the
+    // password, so the other sessions are signed out and this one has to be
+    // updated to stay signed in.
+    auth_session.login(&user).await?;
+    mark_password_change_required(&auth_session.session, false).await?;
+    record_strong_auth(&auth_session.session).await?;
+    track_user_session(user.id, &auth_session.session, db).await?;
     Ok(())
 }
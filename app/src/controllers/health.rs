@@ -0,0 +1,54 @@
+use crate::db::{
+    connection::Database,
+    health::{all_migrations_applied, ping_database, ping_session_store},
+};
+use serde::Serialize;
+use sqlx::Error as SqlxError;
+use tracing::warn;
+
+#[derive(Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+}
+
+#[derive(Serialize)]
+pub struct ReadinessReport {
+    pub database: CheckStatus,
+    pub migrations: CheckStatus,
+    pub session_store: CheckStatus,
+}
+
+impl ReadinessReport {
+    pub fn is_ready(&self) -> bool {
+        [&self.database, &self.migrations, &self.session_store]
+            .iter()
+            .all(|status| **status == CheckStatus::Ok)
+    }
+}
+
+pub async fn check_readiness(db: &Database) -> ReadinessReport {
+    ReadinessReport {
+        database: get_check_status("Database", ping_database(db).await),
+        migrations: match all_migrations_applied(db).await {
+            Ok(true) => CheckStatus::Ok,
+            Ok(false) => {
+                warn!("Readiness check failed: not all migrations are applied");
+                CheckStatus::Failed
+            }
+            Err(e) => get_check_status("Migrations", Err(e)),
+        },
+        session_store: get_check_status("Session store", ping_session_store(db).await),
+    }
+}
+
+fn get_check_status(check: &str, result: Result<(), SqlxError>) -> CheckStatus {
+    match result {
+        Ok(_) => CheckStatus::Ok,
+        Err(e) => {
+            warn!("{} readiness check failed: {:?}", check, e);
+            CheckStatus::Failed
+        }
+    }
+}
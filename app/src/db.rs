@@ -1,2 +1,6 @@
 pub mod connection;
+pub mod data_export;
+pub mod health;
+pub mod session;
+pub mod sign_in_event;
 pub mod user;
@@ -0,0 +1,212 @@
+use crate::db::connection::Database;
+use sqlx::{query, query_as, query_scalar, Error as SqlxError};
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FormatResult},
+};
+use tracing::instrument;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataExportStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+impl From<String> for DataExportStatus {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "ready" => DataExportStatus::Ready,
+            "failed" => DataExportStatus::Failed,
+            _ => DataExportStatus::Pending,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DataExport {
+    pub id: i32,
+    pub status: DataExportStatus,
+    // Unix timestamp, the export is deleted afterwards
+    pub expires_at: i64,
+}
+
+#[derive(Debug)]
+pub struct DataExportError(SqlxError);
+
+impl Error for DataExportError {}
+
+impl Display for DataExportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        write!(f, "Database error: {}", self.0)
+    }
+}
+
+impl From<SqlxError> for DataExportError {
+    fn from(value: SqlxError) -> Self {
+        DataExportError(value)
+    }
+}
+
+// Users have a single export, which a new request replaces with a new id, so
+// the links to the previous one stop working. Returns nothing when the export
+// is still pending and not older than the timeout.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn create_data_export(
+    user_id: i32,
+    expiry_hours: i32,
+    timeout_minutes: i32,
+    db: &Database,
+) -> Result<Option<DataExport>, DataExportError> {
+    let export = query_as!(
+        DataExport,
+        r#"INSERT INTO data_exports (user_id, expires_at)
+        VALUES ($1, CURRENT_TIMESTAMP + make_interval(hours => $2))
+        ON CONFLICT (user_id) DO UPDATE SET
+            id = DEFAULT,
+            status = DEFAULT,
+            data = NULL,
+            created_at = DEFAULT,
+            expires_at = EXCLUDED.expires_at
+        WHERE data_exports.status <> 'pending'
+            OR data_exports.created_at <= CURRENT_TIMESTAMP - make_interval(mins => $3)
+        RETURNING id, status, EXTRACT(EPOCH FROM expires_at)::BIGINT AS "expires_at!""#,
+        user_id,
+        expiry_hours,
+        timeout_minutes
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(export)
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_data_export(
+    user_id: i32,
+    db: &Database,
+) -> Result<Option<DataExport>, DataExportError> {
+    let export = query_as!(
+        DataExport,
+        r#"SELECT id, status, EXTRACT(EPOCH FROM expires_at)::BIGINT AS "expires_at!"
+        FROM data_exports
+        WHERE user_id = $1 AND expires_at > CURRENT_TIMESTAMP"#,
+        user_id
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(export)
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn complete_data_export(
+    id: i32,
+    data: &str,
+    db: &Database,
+) -> Result<(), DataExportError> {
+    query!(
+        "UPDATE data_exports SET status = 'ready', data = $1 WHERE id = $2",
+        data,
+        id
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn fail_data_export(id: i32, db: &Database) -> Result<(), DataExportError> {
+    query!(
+        "UPDATE data_exports SET status = 'failed' WHERE id = $1",
+        id
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+// Generation runs in a task of the server, so the exports it didn't finish
+// before a restart or a crash would stay pending for good otherwise
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn fail_stale_data_exports(
+    timeout_minutes: i32,
+    db: &Database,
+) -> Result<u64, DataExportError> {
+    let result = query!(
+        "UPDATE data_exports SET status = 'failed'
+        WHERE status = 'pending' AND created_at <= CURRENT_TIMESTAMP - make_interval(mins => $1)",
+        timeout_minutes
+    )
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_data_export_data(
+    id: i32,
+    db: &Database,
+) -> Result<Option<String>, DataExportError> {
+    let data = query_scalar!(
+        r#"SELECT data AS "data!"
+        FROM data_exports
+        WHERE id = $1 AND status = 'ready' AND expires_at > CURRENT_TIMESTAMP"#,
+        id
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(data)
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn delete_expired_data_exports(db: &Database) -> Result<u64, DataExportError> {
+    let result = query!("DELETE FROM data_exports WHERE expires_at <= CURRENT_TIMESTAMP")
+        .execute(db)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+// Everything stored about the user except the password hashes. Sessions are
+// passed by id, as the session table is created by the session store itself
+// (hence no query macro). There are no other user tokens to include.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn build_user_data_export(
+    user_id: i32,
+    session_ids: &[String],
+    db: &Database,
+) -> Result<String, DataExportError> {
+    let data = query_scalar(
+        r#"SELECT jsonb_pretty(jsonb_build_object(
+            'exported_at', CURRENT_TIMESTAMP,
+            'profile', (
+                SELECT jsonb_build_object(
+                    'id', id,
+                    'email', email,
+                    'created_at', created_at,
+                    'password_changed_at', password_changed_at,
+                    'password_history_size', (
+                        SELECT COUNT(*) FROM password_history WHERE user_id = $1
+                    ),
+                    'scheduled_deletion_at', scheduled_deletion_at
+                )
+                FROM users WHERE id = $1
+            ),
+            'sign_in_history', COALESCE((
+                SELECT jsonb_agg(jsonb_build_object(
+                    'signed_in_at', created_at,
+                    'ip_address', ip_address,
+                    'user_agent', user_agent
+                ) ORDER BY created_at DESC)
+                FROM sign_in_events WHERE user_id = $1
+            ), '[]'::jsonb),
+            'sessions', COALESCE((
+                SELECT jsonb_agg(jsonb_build_object('expires_at', expiry_date) ORDER BY expiry_date)
+                FROM "tower_sessions"."session" WHERE id = ANY($2)
+            ), '[]'::jsonb)
+        ))"#,
+    )
+    .bind(user_id)
+    .bind(session_ids)
+    .fetch_one(db)
+    .await?;
+    Ok(data)
+}
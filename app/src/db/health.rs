@@ -0,0 +1,26 @@
+use crate::db::connection::Database;
+use sqlx::{migrate, query, query_scalar, Error as SqlxError};
+
+pub async fn ping_database(db: &Database) -> Result<(), SqlxError> {
+    query("SELECT 1").execute(db).await?;
+    Ok(())
+}
+
+pub async fn all_migrations_applied(db: &Database) -> Result<bool, SqlxError> {
+    let applied: Vec<i64> =
+        query_scalar("SELECT version FROM _sqlx_migrations WHERE success = true")
+            .fetch_all(db)
+            .await?;
+    let all_applied = migrate!()
+        .iter()
+        .all(|migration| applied.contains(&migration.version));
+    Ok(all_applied)
+}
+
+// The table is created by the session store itself, hence no query macro
+pub async fn ping_session_store(db: &Database) -> Result<(), SqlxError> {
+    query(r#"SELECT 1 FROM "tower_sessions"."session" LIMIT 1"#)
+        .fetch_optional(db)
+        .await?;
+    Ok(())
+}
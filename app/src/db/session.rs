@@ -0,0 +1,104 @@
+use crate::db::connection::Database;
+use sqlx::{query, query_scalar, Error as SqlxError};
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FormatResult},
+};
+use tower_sessions::{session::Error as SessionError, Session};
+
+#[derive(Debug)]
+pub enum TrackSessionError {
+    MissingIdError,
+    SessionError(SessionError),
+    DatabaseError(SqlxError),
+}
+
+impl Error for TrackSessionError {}
+
+impl Display for TrackSessionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        match self {
+            TrackSessionError::MissingIdError => write!(f, "Session has no id after saving"),
+            TrackSessionError::SessionError(e) => write!(f, "Session error: {}", e),
+            TrackSessionError::DatabaseError(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<SessionError> for TrackSessionError {
+    fn from(value: SessionError) -> Self {
+        TrackSessionError::SessionError(value)
+    }
+}
+
+impl From<SqlxError> for TrackSessionError {
+    fn from(value: SqlxError) -> Self {
+        TrackSessionError::DatabaseError(value)
+    }
+}
+
+// Sessions are stored as opaque records, so the signed in ones are mapped to
+// their users to find them later, e.g. to sign them out on account deletion.
+// Logging in cycles the session id and the new one is only known once the
+// session is saved, hence it's saved right away.
+pub async fn track_user_session(
+    user_id: i32,
+    session: &Session,
+    db: &Database,
+) -> Result<(), TrackSessionError> {
+    session.save().await?;
+    let session_id = session.id().ok_or(TrackSessionError::MissingIdError)?;
+    query!(
+        "INSERT INTO user_sessions (user_id, session_id) VALUES ($1, $2)
+        ON CONFLICT DO NOTHING",
+        user_id,
+        session_id.to_string()
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+// The session table is created by the store itself, hence no query macros
+// for the queries which join it.
+pub async fn get_user_session_ids(
+    user_ids: &[i32],
+    db: &Database,
+) -> Result<Vec<String>, SqlxError> {
+    query_scalar(
+        r#"SELECT session.id FROM user_sessions
+        JOIN "tower_sessions"."session" AS session ON session.id = user_sessions.session_id
+        WHERE user_sessions.user_id = ANY($1)"#,
+    )
+    .bind(user_ids)
+    .fetch_all(db)
+    .await
+}
+
+pub async fn delete_user_sessions(user_ids: &[i32], db: &Database) -> Result<u64, SqlxError> {
+    let result = query(
+        r#"WITH deleted AS (
+            DELETE FROM user_sessions WHERE user_id = ANY($1) RETURNING session_id
+        )
+        DELETE FROM "tower_sessions"."session" WHERE id IN (SELECT session_id FROM deleted)"#,
+    )
+    .bind(user_ids)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+// Signed out and expired sessions are deleted by the store, which doesn't
+// know about the mapping
+pub async fn delete_stale_user_sessions(db: &Database) -> Result<u64, SqlxError> {
+    let result = query(
+        r#"DELETE FROM user_sessions
+        WHERE NOT EXISTS (
+            SELECT 1 FROM "tower_sessions"."session" AS session
+            WHERE session.id = user_sessions.session_id
+        )"#,
+    )
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
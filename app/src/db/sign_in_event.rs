@@ -0,0 +1,21 @@
+use crate::db::connection::Database;
+use sqlx::{query, Error as SqlxError};
+use tracing::instrument;
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn record_sign_in_event(
+    user_id: i32,
+    ip_address: Option<&str>,
+    user_agent: Option<&str>,
+    db: &Database,
+) -> Result<(), SqlxError> {
+    query!(
+        "INSERT INTO sign_in_events (user_id, ip_address, user_agent) VALUES ($1, $2, $3)",
+        user_id,
+        ip_address,
+        user_agent
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
@@ -1,14 +1,17 @@
 use crate::db::connection::Database;
-use sqlx::{query_as, Error as SqlxError};
+use sqlx::{query, query_as, query_scalar, Error as SqlxError};
 use std::{
     error::Error,
     fmt::{Debug as FormatDebug, Display, Formatter, Result as FormatResult},
 };
+use tracing::instrument;
 
 #[derive(Clone)]
 pub struct AuthUser {
     pub id: i32,
     pub password: String,
+    // Encoded `session_version`, which only changes with the password itself
+    pub session_auth_hash: Vec<u8>,
 }
 
 impl FormatDebug for AuthUser {
@@ -55,13 +58,22 @@ impl From<SqlxError> for CreateUserError {
     }
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn create_user(
     data: CreateUserData<'_>,
     db: &Database,
 ) -> Result<AuthUser, CreateUserError> {
     let user = query_as!(
         AuthUser,
-        "INSERT INTO users (email, password) VALUES ($1, $2) RETURNING id, password",
+        r#"WITH created AS (
+            INSERT INTO users (email, password) VALUES ($1, $2)
+            RETURNING id, password, session_version
+        ), history AS (
+            INSERT INTO password_history (user_id, password) SELECT id, password FROM created
+        )
+        SELECT id AS "id!", password AS "password!",
+            int4send(session_version) AS "session_auth_hash!"
+        FROM created"#,
         data.email,
         data.password,
     )
@@ -87,23 +99,35 @@ impl From<SqlxError> for GetUserError {
     }
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn get_auth_user_by_id(
     id: &i32,
     db: &Database,
 ) -> Result<Option<AuthUser>, GetUserError> {
-    let user = query_as!(AuthUser, "SELECT id, password FROM users WHERE id = $1", id)
-        .fetch_optional(db)
-        .await?;
+    // Sessions of users scheduled for deletion are no longer valid
+    let user = query_as!(
+        AuthUser,
+        r#"SELECT id, password, int4send(session_version) AS "session_auth_hash!"
+        FROM users WHERE id = $1 AND scheduled_deletion_at IS NULL"#,
+        id
+    )
+    .fetch_optional(db)
+    .await?;
     Ok(user)
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
 pub async fn get_auth_user_by_email(
     email: &str,
     db: &Database,
 ) -> Result<Option<AuthUser>, GetUserError> {
+    // Users scheduled for deletion can still sign in to cancel it
     let user = query_as!(
         AuthUser,
-        "SELECT id, password FROM users WHERE email = $1",
+        r#"SELECT id, password, int4send(session_version) AS "session_auth_hash!"
+        FROM users
+        WHERE email = $1
+            AND (scheduled_deletion_at IS NULL OR scheduled_deletion_at > CURRENT_TIMESTAMP)"#,
         email
     )
     .fetch_optional(db)
@@ -111,6 +135,240 @@ pub async fn get_auth_user_by_email(
     Ok(user)
 }
 
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn user_with_email_exists(email: &str, db: &Database) -> Result<bool, GetUserError> {
+    let exists = query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM users WHERE email = $1) AS "exists!""#,
+        email
+    )
+    .fetch_one(db)
+    .await?;
+    Ok(exists)
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_user_email(id: i32, db: &Database) -> Result<Option<String>, GetUserError> {
+    let email = query_scalar!("SELECT email FROM users WHERE id = $1", id)
+        .fetch_optional(db)
+        .await?;
+    Ok(email)
+}
+
+// Most recent first, including the current password
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn get_password_history(
+    user_id: i32,
+    limit: i64,
+    db: &Database,
+) -> Result<Vec<String>, GetUserError> {
+    let passwords = query_scalar!(
+        "SELECT password FROM password_history WHERE user_id = $1 ORDER BY id DESC LIMIT $2",
+        user_id,
+        limit
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(passwords)
+}
+
+// A `max_age_days` of 0 disables the expiry
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn user_must_change_password(
+    id: i32,
+    max_age_days: i32,
+    db: &Database,
+) -> Result<bool, GetUserError> {
+    let must_change = query_scalar!(
+        r#"SELECT password_change_required OR (
+            $2 > 0
+            AND NOT password_expiry_exempt
+            AND password_changed_at < CURRENT_TIMESTAMP - make_interval(days => $2)
+        ) AS "must_change!"
+        FROM users WHERE id = $1"#,
+        id,
+        max_age_days
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(must_change.unwrap_or(false))
+}
+
+#[derive(Debug)]
+pub struct UpdateUserError(SqlxError);
+
+impl Error for UpdateUserError {}
+
+impl Display for UpdateUserError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        write!(f, "Database error: {}", self.0)
+    }
+}
+
+impl From<SqlxError> for UpdateUserError {
+    fn from(value: SqlxError) -> Self {
+        UpdateUserError(value)
+    }
+}
+
+// Replaces the hash of the same password (e.g. after upgrading the hashing
+// params), so its history entry is updated instead of adding a new one.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn update_user_password(
+    id: i32,
+    password: &str,
+    db: &Database,
+) -> Result<(), UpdateUserError> {
+    query!(
+        "WITH history AS (
+            UPDATE password_history SET password = $1
+            WHERE user_id = $2 AND password = (SELECT password FROM users WHERE id = $2)
+        )
+        UPDATE users SET password = $1 WHERE id = $2",
+        password,
+        id
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn change_user_password(
+    id: i32,
+    password: &str,
+    db: &Database,
+) -> Result<Option<AuthUser>, UpdateUserError> {
+    let user = query_as!(
+        AuthUser,
+        r#"WITH updated AS (
+            UPDATE users
+            SET password = $1,
+                password_changed_at = CURRENT_TIMESTAMP,
+                password_change_required = FALSE,
+                session_version = session_version + 1
+            WHERE id = $2
+            RETURNING id, password, session_version
+        ), history AS (
+            INSERT INTO password_history (user_id, password) SELECT id, password FROM updated
+        )
+        SELECT id AS "id!", password AS "password!",
+            int4send(session_version) AS "session_auth_hash!"
+        FROM updated"#,
+        password,
+        id
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(user)
+}
+
+// Returns false when the user doesn't exist
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn set_password_change_required(
+    id: i32,
+    required: bool,
+    db: &Database,
+) -> Result<bool, UpdateUserError> {
+    let result = query!(
+        "UPDATE users SET password_change_required = $1 WHERE id = $2",
+        required,
+        id
+    )
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// Returns false when the user doesn't exist
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn set_password_expiry_exempt(
+    id: i32,
+    exempt: bool,
+    db: &Database,
+) -> Result<bool, UpdateUserError> {
+    let result = query!(
+        "UPDATE users SET password_expiry_exempt = $1 WHERE id = $2",
+        exempt,
+        id
+    )
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn schedule_user_deletion(
+    id: i32,
+    grace_period_days: i32,
+    db: &Database,
+) -> Result<bool, UpdateUserError> {
+    let result = query!(
+        "UPDATE users
+        SET scheduled_deletion_at = CURRENT_TIMESTAMP + make_interval(days => $2)
+        WHERE id = $1",
+        id,
+        grace_period_days
+    )
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// Returns whether the deletion was scheduled, or `None` when the user is past
+// the grace period or already purged. The row is locked, so the purge can't
+// delete the user in between and it's checked together with the cancelling.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn cancel_user_deletion(id: i32, db: &Database) -> Result<Option<bool>, UpdateUserError> {
+    let deletion_cancelled = query_scalar!(
+        r#"WITH previous AS (
+            SELECT id, scheduled_deletion_at FROM users WHERE id = $1 FOR UPDATE
+        )
+        UPDATE users SET scheduled_deletion_at = NULL
+        FROM previous
+        WHERE users.id = previous.id
+            AND (
+                previous.scheduled_deletion_at IS NULL
+                OR previous.scheduled_deletion_at > CURRENT_TIMESTAMP
+            )
+        RETURNING previous.scheduled_deletion_at IS NOT NULL AS "deletion_cancelled!""#,
+        id
+    )
+    .fetch_optional(db)
+    .await?;
+    Ok(deletion_cancelled)
+}
+
+#[derive(Debug)]
+pub struct DeleteUserError(SqlxError);
+
+impl Error for DeleteUserError {}
+
+impl Display for DeleteUserError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        write!(f, "Database error: {}", self.0)
+    }
+}
+
+impl From<SqlxError> for DeleteUserError {
+    fn from(value: SqlxError) -> Self {
+        DeleteUserError(value)
+    }
+}
+
+// Related rows (e.g. the password history) are deleted by cascade, returns
+// the ids of the deleted users.
+#[instrument(skip_all, fields(db.system = "postgresql"))]
+pub async fn delete_users_past_scheduled_deletion(
+    db: &Database,
+) -> Result<Vec<i32>, DeleteUserError> {
+    let ids = query_scalar!(
+        "DELETE FROM users WHERE scheduled_deletion_at <= CURRENT_TIMESTAMP RETURNING id"
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(ids)
+}
+
 #[cfg(test)]
 mod tests {
     use super::AuthUser;
@@ -120,6 +378,7 @@ mod tests {
         let user = AuthUser {
             id: 1,
             password: String::from("password123"),
+            session_auth_hash: vec![0, 0, 0, 1],
         };
 
         let log = format!("{:?}", user);
@@ -0,0 +1,196 @@
+use crate::{
+    config::Config,
+    db::{
+        connection::{setup_db_pool, Database},
+        user::{create_user, CreateUserData, CreateUserError},
+    },
+    libs::{password::is_supported_hash, redact::Redacted, validation::is_valid_email},
+};
+use serde::Deserialize;
+use std::{
+    error::Error,
+    fmt::{Debug as FormatDebug, Display, Formatter, Result as FormatResult},
+    fs::read_to_string,
+    io::Error as IoError,
+    path::Path,
+};
+
+// Users migrated from another system with their already hashed passwords,
+// non-argon2 hashes are upgraded when the user signs in for the first time.
+#[derive(Deserialize)]
+pub struct ImportedUser {
+    pub email: String,
+    pub password: String,
+}
+
+impl FormatDebug for ImportedUser {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        f.debug_struct("ImportedUser")
+            .field("email", &Redacted(&self.email))
+            .field("password", &"********")
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportUsersError {
+    ReadError(IoError),
+    CsvError(csv::Error),
+    JsonError(serde_json::Error),
+    UnknownFormatError,
+}
+
+impl Error for ImportUsersError {}
+
+impl Display for ImportUsersError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        match self {
+            ImportUsersError::ReadError(e) => write!(f, "Read error: {}", e),
+            ImportUsersError::CsvError(e) => write!(f, "CSV error: {}", e),
+            ImportUsersError::JsonError(e) => write!(f, "JSON error: {}", e),
+            ImportUsersError::UnknownFormatError => {
+                write!(f, "Unknown format, expected a .csv or .json file")
+            }
+        }
+    }
+}
+
+impl From<IoError> for ImportUsersError {
+    fn from(value: IoError) -> Self {
+        ImportUsersError::ReadError(value)
+    }
+}
+
+impl From<csv::Error> for ImportUsersError {
+    fn from(value: csv::Error) -> Self {
+        ImportUsersError::CsvError(value)
+    }
+}
+
+impl From<serde_json::Error> for ImportUsersError {
+    fn from(value: serde_json::Error) -> Self {
+        ImportUsersError::JsonError(value)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportFormat {
+    Csv,
+    Json,
+}
+
+impl ImportFormat {
+    pub fn from_path(path: &Path) -> Result<Self, ImportUsersError> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => Ok(ImportFormat::Csv),
+            Some("json") => Ok(ImportFormat::Json),
+            _ => Err(ImportUsersError::UnknownFormatError),
+        }
+    }
+}
+
+// CSV files need an `email,password` header, JSON files an array of objects
+// with the same fields.
+pub fn parse_users(
+    content: &str,
+    format: ImportFormat,
+) -> Result<Vec<ImportedUser>, ImportUsersError> {
+    let users = match format {
+        ImportFormat::Csv => csv::Reader::from_reader(content.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()?,
+        ImportFormat::Json => serde_json::from_str(content)?,
+    };
+    Ok(users)
+}
+
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub skipped: Vec<String>,
+}
+
+// Invalid and already existing users are skipped, so the import can be rerun
+// after fixing the reported entries.
+pub async fn import_users(users: Vec<ImportedUser>, db: &Database) -> ImportSummary {
+    let mut summary = ImportSummary::default();
+    for (index, user) in users.iter().enumerate() {
+        let entry = index + 1;
+        if !is_valid_email(&user.email) {
+            summary
+                .skipped
+                .push(format!("Entry {}: invalid email", entry));
+            continue;
+        }
+        if !is_supported_hash(&user.password) {
+            summary
+                .skipped
+                .push(format!("Entry {}: unsupported password hash", entry));
+            continue;
+        }
+        let data = CreateUserData {
+            email: &user.email,
+            password: &user.password,
+        };
+        match create_user(data, db).await {
+            Ok(_) => summary.imported += 1,
+            Err(CreateUserError::EmailAlreadyExistsError) => summary
+                .skipped
+                .push(format!("Entry {}: email already exists", entry)),
+            Err(e) => summary.skipped.push(format!("Entry {}: {}", entry, e)),
+        }
+    }
+    summary
+}
+
+pub async fn run_import_users(
+    config: Config,
+    path: &Path,
+) -> Result<ImportSummary, ImportUsersError> {
+    let format = ImportFormat::from_path(path)?;
+    let users = parse_users(&read_to_string(path)?, format)?;
+    let db = setup_db_pool(&config.db.url, config.db.pool_max_connections).await;
+    Ok(import_users(users, &db).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_users, ImportFormat, ImportUsersError};
+    use std::path::Path;
+
+    #[test]
+    fn detect_format_from_extension() {
+        assert_eq!(
+            ImportFormat::from_path(Path::new("users.csv")).unwrap(),
+            ImportFormat::Csv
+        );
+        assert_eq!(
+            ImportFormat::from_path(Path::new("users.json")).unwrap(),
+            ImportFormat::Json
+        );
+        assert!(matches!(
+            ImportFormat::from_path(Path::new("users.txt")),
+            Err(ImportUsersError::UnknownFormatError)
+        ));
+    }
+
+    #[test]
+    fn parse_csv_and_json() {
+        let csv = "email,password\ntest@example.com,$2b$04$hash\n";
+        let json = r#"[{"email": "test@example.com", "password": "$2b$04$hash"}]"#;
+
+        for (content, format) in [(csv, ImportFormat::Csv), (json, ImportFormat::Json)] {
+            let users = parse_users(content, format).unwrap();
+
+            assert_eq!(users.len(), 1);
+            assert_eq!(users[0].email, "test@example.com");
+            assert_eq!(users[0].password, "$2b$04$hash");
+        }
+    }
+
+    #[test]
+    fn reject_malformed_file() {
+        assert!(parse_users("email\ntest@example.com\n", ImportFormat::Csv).is_err());
+        assert!(parse_users(r#"{"email": "test@example.com"}"#, ImportFormat::Json).is_err());
+    }
+}
@@ -1,9 +1,12 @@
+pub mod cli;
 pub mod config;
 pub mod db;
+pub mod import;
 pub mod libs;
 pub mod server;
+pub mod tracing;
 
 mod api;
 mod controllers;
+mod metrics;
 mod state;
-mod tracing;
@@ -1,5 +1,17 @@
+pub mod admin_token;
+pub mod archive;
 pub mod asset;
 pub mod auth;
+pub mod breached_password;
+pub mod flash;
+pub mod health;
 pub mod password;
+pub mod password_policy;
+pub mod password_rotation;
+pub mod rate_limit;
+pub mod recent_auth;
+pub mod redact;
+pub mod secret_key;
+pub mod session_lifetime;
 pub mod signal;
 pub mod validation;
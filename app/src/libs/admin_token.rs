@@ -0,0 +1,45 @@
+use axum::http::{header::AUTHORIZATION, HeaderMap};
+use sha2::{Digest, Sha256};
+
+// Long enough not to be guessed by trying
+pub const MIN_ADMIN_TOKEN_LENGTH: usize = 32;
+
+// Expects the `Authorization: Bearer <token>` header. The digests are compared
+// instead of the tokens, so the time taken tells nothing about the token.
+pub fn has_admin_token(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map_or(false, |provided| {
+            Sha256::digest(provided.as_bytes()) == Sha256::digest(token.as_bytes())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::has_admin_token;
+    use axum::http::{header::AUTHORIZATION, HeaderMap};
+
+    const TOKEN: &str = "0123456789abcdef0123456789abcdef";
+
+    fn create_headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, authorization.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn accept_matching_bearer_token() {
+        let headers = create_headers(&format!("Bearer {}", TOKEN));
+
+        assert!(has_admin_token(&headers, TOKEN));
+    }
+
+    #[test]
+    fn reject_missing_or_wrong_token() {
+        assert!(!has_admin_token(&HeaderMap::new(), TOKEN));
+        assert!(!has_admin_token(&create_headers(TOKEN), TOKEN));
+        assert!(!has_admin_token(&create_headers("Bearer wrong"), TOKEN));
+    }
+}
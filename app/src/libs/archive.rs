@@ -0,0 +1,33 @@
+use std::io::{Cursor, Write};
+use zip::{result::ZipError, write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+// Creates a zip archive containing a single file
+pub fn zip_file(name: &str, content: &[u8]) -> Result<Vec<u8>, ZipError> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    writer.start_file(name, options)?;
+    writer.write_all(content)?;
+    Ok(writer.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::zip_file;
+    use std::io::{Cursor, Read};
+    use zip::ZipArchive;
+
+    #[test]
+    fn zip_single_file() {
+        let archive = zip_file("data.json", br#"{"email": "test@example.com"}"#).unwrap();
+
+        let mut archive = ZipArchive::new(Cursor::new(archive)).unwrap();
+        let mut content = String::new();
+        archive
+            .by_name("data.json")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(archive.len(), 1);
+        assert_eq!(content, r#"{"email": "test@example.com"}"#);
+    }
+}
@@ -1,12 +1,16 @@
 use crate::{
     db::{
         connection::Database,
-        user::{get_auth_user_by_email, get_auth_user_by_id, AuthUser, GetUserError},
+        user::{
+            get_auth_user_by_email, get_auth_user_by_id, update_user_password, AuthUser,
+            GetUserError,
+        },
     },
     libs::password::{
         hash_password_in_separate_thread, verify_password_in_separate_thread, HashPasswordError,
-        VerifyPasswordError,
+        PasswordHashing, VerifyPasswordError,
     },
+    libs::redact::Redacted,
 };
 use async_trait::async_trait;
 use axum_login::{
@@ -15,18 +19,42 @@ use axum_login::{
 };
 use std::{
     error::Error,
-    fmt::{Display, Formatter, Result as FormatResult},
+    fmt::{Debug as FormatDebug, Display, Formatter, Result as FormatResult},
 };
-use tracing::debug;
+use tracing::{debug, error};
 
 #[derive(Clone)]
 pub struct Backend {
     db: Database,
+    password_hashing: PasswordHashing,
 }
 
 impl Backend {
-    pub fn new(db: Database) -> Self {
-        Self { db }
+    pub fn new(db: Database, password_hashing: PasswordHashing) -> Self {
+        Self {
+            db,
+            password_hashing,
+        }
+    }
+
+    // Upgrade the hash to the configured parameters while the plain password
+    // is at hand. Failing to do so must not prevent the user from signing in.
+    async fn rehash_password_if_outdated(&self, user: &mut AuthUser, password: String) {
+        if !self.password_hashing.needs_rehash(&user.password) {
+            return;
+        }
+        let hashed_password =
+            match hash_password_in_separate_thread(password, &self.password_hashing).await {
+                Ok(hashed_password) => hashed_password,
+                Err(e) => {
+                    error!("Failed to rehash password: {}", e);
+                    return;
+                }
+            };
+        match update_user_password(user.id, &hashed_password, &self.db).await {
+            Ok(()) => user.password = hashed_password,
+            Err(e) => error!("Failed to update rehashed password: {}", e),
+        }
     }
 }
 
@@ -38,9 +66,10 @@ impl BaseAuthUser for AuthUser {
     }
 
     fn session_auth_hash(&self) -> &[u8] {
-        // Use the password hash as the auth hash, so when user changes their
-        // password the auth session becomes invalid.
-        self.password.as_bytes()
+        // The version is bumped when user changes their password, so the
+        // other auth sessions become invalid. Rehashing the same password
+        // keeps them.
+        &self.session_auth_hash
     }
 }
 
@@ -51,6 +80,16 @@ pub enum AuthenticationError {
     HashPasswordError(HashPasswordError),
 }
 
+impl AuthenticationError {
+    pub fn is_hashing_busy(&self) -> bool {
+        match self {
+            AuthenticationError::VerifyPasswordError(e) => e.is_busy(),
+            AuthenticationError::HashPasswordError(e) => e.is_busy(),
+            AuthenticationError::GetUserError(_) => false,
+        }
+    }
+}
+
 impl Error for AuthenticationError {}
 
 impl Display for AuthenticationError {
@@ -92,6 +131,15 @@ pub struct Credentials {
     pub password: String,
 }
 
+impl FormatDebug for Credentials {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        f.debug_struct("Credentials")
+            .field("email", &Redacted(&self.email))
+            .field("password", &Redacted(&self.password))
+            .finish()
+    }
+}
+
 #[async_trait]
 impl AuthnBackend for Backend {
     type User = AuthUser;
@@ -104,17 +152,27 @@ impl AuthnBackend for Backend {
     ) -> Result<Option<Self::User>, Self::Error> {
         let user_res = match get_auth_user_by_email(&creds.email, &self.db).await? {
             None => {
-                debug!("User with email {} not found", creds.email);
+                debug!("User with email {} not found", Redacted(&creds.email));
                 // Run the password hasher to mitigate timing attack
-                hash_password_in_separate_thread(creds.password).await?;
+                hash_password_in_separate_thread(creds.password, &self.password_hashing).await?;
                 None
             }
-            Some(user) => {
-                if verify_password_in_separate_thread(creds.password, user.password.clone()).await?
+            Some(mut user) => {
+                if verify_password_in_separate_thread(
+                    creds.password.clone(),
+                    user.password.clone(),
+                    &self.password_hashing,
+                )
+                .await?
                 {
+                    self.rehash_password_if_outdated(&mut user, creds.password)
+                        .await;
                     Some(user)
                 } else {
-                    debug!("Invalid password for user with email {}", creds.email);
+                    debug!(
+                        "Invalid password for user with email {}",
+                        Redacted(&creds.email)
+                    );
                     None
                 }
             }
@@ -134,3 +192,21 @@ pub async fn is_anonymous(auth_session: AuthSession) -> bool {
 
 pub type AuthSession = BaseAuthSession<Backend>;
 pub type AuthError = BaseError<Backend>;
+
+#[cfg(test)]
+mod tests {
+    use super::Credentials;
+
+    #[test]
+    fn credentials_are_not_logged() {
+        let credentials = Credentials {
+            email: String::from("test@example.com"),
+            password: String::from("password123"),
+        };
+
+        let log = format!("{:?}", credentials);
+
+        assert!(!log.contains("test@example.com"));
+        assert!(!log.contains("password123"));
+    }
+}
@@ -0,0 +1,139 @@
+use async_trait::async_trait;
+use sha1::{Digest, Sha1};
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FormatResult},
+    io::{Error as IoError, ErrorKind},
+    path::PathBuf,
+};
+use tokio::fs::read_to_string;
+
+const PREFIX_LENGTH: usize = 5;
+
+#[derive(Debug)]
+pub enum BreachCheckError {
+    MissingRangeError(String),
+    ReadError(IoError),
+}
+
+impl Error for BreachCheckError {}
+
+impl Display for BreachCheckError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        match self {
+            BreachCheckError::MissingRangeError(prefix) => {
+                write!(f, "Range {} is missing in the dataset", prefix)
+            }
+            BreachCheckError::ReadError(e) => write!(f, "Read error: {}", e),
+        }
+    }
+}
+
+impl From<IoError> for BreachCheckError {
+    fn from(value: IoError) -> Self {
+        BreachCheckError::ReadError(value)
+    }
+}
+
+// Source of Have I Been Pwned style ranges (k-anonymity): only the first 5
+// hex characters of the SHA-1 hash leave the caller, the source returns all
+// known suffixes for them as `SUFFIX:COUNT` lines. A remote range API can be
+// plugged in by implementing it.
+#[async_trait]
+pub trait BreachedPasswordRanges: Send + Sync {
+    async fn get_range(&self, prefix: &str) -> Result<String, BreachCheckError>;
+}
+
+// Ranges downloaded to a directory as one file per prefix (e.g. `5BAA6.txt`,
+// the layout of the HIBP downloader), so the check works fully offline.
+pub struct LocalBreachedPasswordRanges {
+    directory: PathBuf,
+}
+
+impl LocalBreachedPasswordRanges {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+}
+
+#[async_trait]
+impl BreachedPasswordRanges for LocalBreachedPasswordRanges {
+    async fn get_range(&self, prefix: &str) -> Result<String, BreachCheckError> {
+        for file_name in [format!("{}.txt", prefix), prefix.to_owned()] {
+            match read_to_string(self.directory.join(file_name)).await {
+                Ok(range) => return Ok(range),
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        // Every prefix exists in the full dataset, so it's incomplete
+        Err(BreachCheckError::MissingRangeError(prefix.to_owned()))
+    }
+}
+
+pub async fn count_password_breaches(
+    ranges: &dyn BreachedPasswordRanges,
+    password: &str,
+) -> Result<u64, BreachCheckError> {
+    let hash = format!("{:X}", Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+    let range = ranges.get_range(prefix).await?;
+    let count = range
+        .lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .find(|(line_suffix, _)| line_suffix.eq_ignore_ascii_case(suffix))
+        .and_then(|(_, count)| count.trim().parse().ok())
+        .unwrap_or(0);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        count_password_breaches, BreachCheckError, BreachedPasswordRanges,
+        LocalBreachedPasswordRanges,
+    };
+    use async_trait::async_trait;
+    use std::fs::write;
+    use tempfile::tempdir;
+
+    // SHA-1 of "password" is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8
+    const PASSWORD_RANGE: &str = "003D68EB55068C33ACE09247EE4C639306B:3\r\n\
+        1E4C9B93F3F0682250B6CF8331B7EE68FD8:9659365\r\n";
+
+    struct StaticRanges;
+
+    #[async_trait]
+    impl BreachedPasswordRanges for StaticRanges {
+        async fn get_range(&self, prefix: &str) -> Result<String, BreachCheckError> {
+            assert_eq!(prefix, "5BAA6");
+            Ok(String::from(PASSWORD_RANGE))
+        }
+    }
+
+    #[tokio::test]
+    async fn count_breaches_from_range() {
+        assert_eq!(
+            count_password_breaches(&StaticRanges, "password")
+                .await
+                .unwrap(),
+            9659365
+        );
+    }
+
+    #[tokio::test]
+    async fn read_range_from_directory() {
+        let directory = tempdir().unwrap();
+        write(directory.path().join("5BAA6.txt"), PASSWORD_RANGE).unwrap();
+        let ranges = LocalBreachedPasswordRanges::new(directory.path().to_owned());
+
+        let count = count_password_breaches(&ranges, "password").await.unwrap();
+        let missing = count_password_breaches(&ranges, "kV9#mRq2!zXp").await;
+
+        assert_eq!(count, 9659365);
+        assert!(matches!(
+            missing,
+            Err(BreachCheckError::MissingRangeError(_))
+        ));
+    }
+}
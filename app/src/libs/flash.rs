@@ -0,0 +1,161 @@
+use async_trait::async_trait;
+use axum::{extract::FromRequestParts, http::request::Parts};
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tower_sessions::{session::Error as SessionError, Session};
+use tracing::error;
+
+const FLASH_MESSAGES_KEY: &str = "flash.messages";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlashLevel {
+    Info,
+    Success,
+    Error,
+}
+
+impl FlashLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlashLevel::Info => "info",
+            FlashLevel::Success => "success",
+            FlashLevel::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FlashMessage {
+    pub level: FlashLevel,
+    pub text: String,
+}
+
+pub async fn push_flash_message(
+    session: &Session,
+    message: FlashMessage,
+) -> Result<(), SessionError> {
+    let mut messages = read_flash_messages(session).await?;
+    messages.push(message);
+    session.insert(FLASH_MESSAGES_KEY, messages).await
+}
+
+pub async fn read_flash_messages(session: &Session) -> Result<Vec<FlashMessage>, SessionError> {
+    let messages = session
+        .get::<Vec<FlashMessage>>(FLASH_MESSAGES_KEY)
+        .await?
+        .unwrap_or_default();
+    Ok(messages)
+}
+
+// Only the delivered messages are removed, so the ones pushed while handling
+// the same request survive until the next page is rendered.
+pub async fn remove_flash_messages(session: &Session, count: usize) -> Result<(), SessionError> {
+    let messages = read_flash_messages(session).await?;
+    let remaining: Vec<FlashMessage> = messages.into_iter().skip(count).collect();
+    if remaining.is_empty() {
+        session
+            .remove::<Vec<FlashMessage>>(FLASH_MESSAGES_KEY)
+            .await?;
+    } else {
+        session.insert(FLASH_MESSAGES_KEY, remaining).await?;
+    }
+    Ok(())
+}
+
+pub struct Flash {
+    session: Session,
+}
+
+impl Flash {
+    pub async fn info(&self, text: &str) {
+        self.push(FlashLevel::Info, text).await
+    }
+
+    pub async fn success(&self, text: &str) {
+        self.push(FlashLevel::Success, text).await
+    }
+
+    pub async fn error(&self, text: &str) {
+        self.push(FlashLevel::Error, text).await
+    }
+
+    async fn push(&self, level: FlashLevel, text: &str) {
+        let message = FlashMessage {
+            level,
+            text: text.to_owned(),
+        };
+        // Flash messages are informative only, so failing to store one
+        // shouldn't fail the whole request.
+        if let Err(e) = push_flash_message(&self.session, message).await {
+            error!("Failed to push flash message: {:?}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Flash
+where
+    S: Send + Sync,
+{
+    type Rejection = <Session as FromRequestParts<S>>::Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state).await?;
+        Ok(Flash { session })
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct PendingFlashMessages {
+    messages: Arc<Vec<FlashMessage>>,
+    delivered: Arc<AtomicBool>,
+}
+
+impl PendingFlashMessages {
+    pub fn new(messages: Vec<FlashMessage>) -> Self {
+        Self {
+            messages: Arc::new(messages),
+            delivered: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    // Called while rendering, the messages are removed from the session once
+    // the response is ready.
+    pub fn deliver(&self) -> &[FlashMessage] {
+        self.delivered.store(true, Ordering::Relaxed);
+        &self.messages
+    }
+
+    pub fn is_delivered(&self) -> bool {
+        self.delivered.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlashLevel, FlashMessage, PendingFlashMessages};
+
+    #[test]
+    fn messages_are_marked_as_delivered() {
+        let messages = PendingFlashMessages::new(vec![FlashMessage {
+            level: FlashLevel::Info,
+            text: String::from("Message"),
+        }]);
+
+        assert!(!messages.is_delivered());
+        assert_eq!(messages.deliver().len(), 1);
+        assert!(messages.is_delivered());
+    }
+}
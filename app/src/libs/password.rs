@@ -1,20 +1,262 @@
+use crate::metrics::{
+    record_password_hashing, record_password_hashing_rejection, record_password_hashing_wait,
+};
 use argon2::{
     password_hash::{
         rand_core::OsRng, Error as Argon2Error, PasswordHash, PasswordHasher, PasswordVerifier,
         SaltString,
     },
-    Argon2,
+    Algorithm, Argon2, Error as ParamsError, KeyId, Params, ParamsBuilder, Version,
 };
+use bcrypt::BcryptError;
+use pbkdf2::Pbkdf2;
+use scrypt::Scrypt;
 use std::{
     error::Error,
-    fmt::{Display, Formatter, Result as FormatResult},
+    fmt::{Debug as FormatDebug, Display, Formatter, Result as FormatResult},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
-use tokio::task::{spawn_blocking, JoinError};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    task::{spawn_blocking, JoinError},
+};
+use tracing::{error, info_span};
+
+pub const MIN_PEPPER_LENGTH: usize = 32;
+
+const DEFAULT_MAX_CONCURRENCY: usize = 4;
+const DEFAULT_MAX_QUEUE_LENGTH: usize = 64;
+
+#[derive(Debug)]
+pub struct HashingBusyError;
+
+impl Error for HashingBusyError {}
+
+impl Display for HashingBusyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        write!(f, "Too many passwords are waiting to be hashed")
+    }
+}
+
+// Each hash takes tens of MiB and a blocking thread, so only a few run at once
+// and a burst of requests beyond the queue is rejected instead of piling up.
+#[derive(Debug)]
+struct HashingLimiter {
+    permits: Arc<Semaphore>,
+    max_queue_length: usize,
+    queue_length: AtomicUsize,
+}
+
+impl HashingLimiter {
+    fn new(max_concurrency: usize, max_queue_length: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrency)),
+            max_queue_length,
+            queue_length: AtomicUsize::new(0),
+        }
+    }
+
+    async fn acquire(&self) -> Result<OwnedSemaphorePermit, HashingBusyError> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            record_password_hashing_wait(Duration::ZERO);
+            return Ok(permit);
+        }
+        let start = Instant::now();
+        let _queued = QueuedHashing::enter(self).ok_or_else(|| {
+            record_password_hashing_rejection();
+            HashingBusyError
+        })?;
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Hashing semaphore is never closed");
+        record_password_hashing_wait(start.elapsed());
+        Ok(permit)
+    }
+}
+
+// Leaves the queue when dropped, also when the request is cancelled while
+// waiting.
+struct QueuedHashing<'a>(&'a AtomicUsize);
+
+impl<'a> QueuedHashing<'a> {
+    fn enter(limiter: &'a HashingLimiter) -> Option<Self> {
+        limiter
+            .queue_length
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |length| {
+                (length < limiter.max_queue_length).then_some(length + 1)
+            })
+            .ok()
+            .map(|_| Self(&limiter.queue_length))
+    }
+}
+
+impl Drop for QueuedHashing<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Secret mixed into argon2 hashes (the argon2 secret parameter) which is kept
+// out of the database. Its id is stored in the hash as the `keyid` parameter,
+// so older peppers are still found when verifying after a rotation.
+#[derive(Clone)]
+pub struct Pepper {
+    id: KeyId,
+    secret: Vec<u8>,
+}
+
+impl Pepper {
+    pub fn new(id: &str, secret: Vec<u8>) -> Result<Self, ParamsError> {
+        Ok(Self {
+            id: KeyId::new(id.as_bytes())?,
+            secret,
+        })
+    }
+
+    pub fn id(&self) -> &[u8] {
+        self.id.as_bytes()
+    }
+}
+
+impl FormatDebug for Pepper {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        f.debug_struct("Pepper")
+            .field("id", &self.id)
+            .field("secret", &"********")
+            .finish()
+    }
+}
+
+// Parameters used for new hashes, hashes created with different ones are
+// still verified (the parameters are stored in the PHC string) and upgraded.
+#[derive(Clone, Debug)]
+pub struct PasswordHashing {
+    algorithm: Algorithm,
+    params: Params,
+    // The first pepper is used for new hashes, the rest only for verifying
+    peppers: Arc<Vec<Pepper>>,
+    limiter: Arc<HashingLimiter>,
+}
+
+impl Default for PasswordHashing {
+    fn default() -> Self {
+        Self::new(Algorithm::default(), Params::default())
+    }
+}
+
+impl PasswordHashing {
+    pub fn new(algorithm: Algorithm, params: Params) -> Self {
+        Self {
+            algorithm,
+            params,
+            peppers: Arc::new(Vec::new()),
+            limiter: Arc::new(HashingLimiter::new(
+                DEFAULT_MAX_CONCURRENCY,
+                DEFAULT_MAX_QUEUE_LENGTH,
+            )),
+        }
+    }
+
+    pub fn with_limits(self, max_concurrency: usize, max_queue_length: usize) -> Self {
+        Self {
+            limiter: Arc::new(HashingLimiter::new(max_concurrency, max_queue_length)),
+            ..self
+        }
+    }
+
+    pub fn with_peppers(self, peppers: Vec<Pepper>) -> Result<Self, ParamsError> {
+        let mut builder = ParamsBuilder::new();
+        builder
+            .m_cost(self.params.m_cost())
+            .t_cost(self.params.t_cost())
+            .p_cost(self.params.p_cost());
+        if let Some(output_len) = self.params.output_len() {
+            builder.output_len(output_len);
+        }
+        if let Some(pepper) = peppers.first() {
+            builder.keyid(pepper.id);
+        }
+        Ok(Self {
+            algorithm: self.algorithm,
+            params: builder.build()?,
+            peppers: Arc::new(peppers),
+            limiter: self.limiter,
+        })
+    }
+
+    fn hasher(&self) -> Result<Argon2<'_>, ParamsError> {
+        match self.peppers.first() {
+            Some(pepper) => Argon2::new_with_secret(
+                &pepper.secret,
+                self.algorithm,
+                Version::default(),
+                self.params.clone(),
+            ),
+            None => Ok(Argon2::new(
+                self.algorithm,
+                Version::default(),
+                self.params.clone(),
+            )),
+        }
+    }
+
+    // The algorithm and parameters are taken from the hash when verifying, only
+    // the pepper has to be picked here.
+    fn verifier(&self, parsed_hash: &PasswordHash) -> Result<Argon2<'_>, VerifyPasswordError> {
+        let keyid = Params::try_from(parsed_hash)
+            .map(|params| params.keyid().to_vec())
+            .unwrap_or_default();
+        if keyid.is_empty() {
+            return Ok(Argon2::default());
+        }
+        let pepper = self
+            .peppers
+            .iter()
+            .find(|pepper| pepper.id() == keyid)
+            .ok_or(VerifyPasswordError::UnknownPepperError)?;
+        let verifier = Argon2::new_with_secret(
+            &pepper.secret,
+            Algorithm::default(),
+            Version::default(),
+            Params::default(),
+        )?;
+        Ok(verifier)
+    }
+
+    pub fn needs_rehash(&self, hashed_password: &str) -> bool {
+        let Ok(parsed_hash) = PasswordHash::new(hashed_password) else {
+            return true;
+        };
+        let Ok(params) = Params::try_from(&parsed_hash) else {
+            return true;
+        };
+        parsed_hash.algorithm != self.algorithm.ident()
+            || parsed_hash.version != Some(Version::default().into())
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+            || params.keyid() != self.params.keyid()
+    }
+}
 
 #[derive(Debug)]
 pub enum HashPasswordError {
     ThreadError(JoinError),
     HashError(Argon2Error),
+    BusyError(HashingBusyError),
+}
+
+impl HashPasswordError {
+    pub fn is_busy(&self) -> bool {
+        matches!(self, HashPasswordError::BusyError(_))
+    }
 }
 
 impl Error for HashPasswordError {}
@@ -24,6 +266,7 @@ impl Display for HashPasswordError {
         match self {
             HashPasswordError::ThreadError(e) => write!(f, "Thread error: {}", e),
             HashPasswordError::HashError(e) => write!(f, "Hash error: {}", e),
+            HashPasswordError::BusyError(e) => write!(f, "Busy error: {}", e),
         }
     }
 }
@@ -40,18 +283,37 @@ impl From<Argon2Error> for HashPasswordError {
     }
 }
 
+impl From<HashingBusyError> for HashPasswordError {
+    fn from(value: HashingBusyError) -> Self {
+        HashPasswordError::BusyError(value)
+    }
+}
+
 pub async fn hash_password_in_separate_thread(
     password: String,
+    hashing: &PasswordHashing,
 ) -> Result<String, HashPasswordError> {
-    let hashed_password = spawn_blocking(move || hash_password(&password.into_bytes())).await??;
+    // The blocking thread doesn't inherit the current span, so it's passed
+    // explicitly to keep the hashing in the request trace.
+    let span = info_span!("hash_password");
+    let permit = hashing.limiter.acquire().await?;
+    let hashing = hashing.clone();
+    let hashed_password = spawn_blocking(move || {
+        let _permit = permit;
+        span.in_scope(|| hash_password(&hashing, &password.into_bytes()))
+    })
+    .await??;
     Ok(hashed_password)
 }
 
-fn hash_password(password: &[u8]) -> Result<String, Argon2Error> {
+fn hash_password(hashing: &PasswordHashing, password: &[u8]) -> Result<String, Argon2Error> {
+    let start = Instant::now();
     let salt = SaltString::generate(&mut OsRng);
-    let hashed_password = Argon2::default()
+    let hashed_password = hashing
+        .hasher()?
         .hash_password(password, &salt)?
         .to_string();
+    record_password_hashing("hash", start.elapsed());
     Ok(hashed_password)
 }
 
@@ -59,6 +321,15 @@ fn hash_password(password: &[u8]) -> Result<String, Argon2Error> {
 pub enum VerifyPasswordError {
     ThreadError(JoinError),
     HashError(Argon2Error),
+    BcryptError(BcryptError),
+    UnknownPepperError,
+    BusyError(HashingBusyError),
+}
+
+impl VerifyPasswordError {
+    pub fn is_busy(&self) -> bool {
+        matches!(self, VerifyPasswordError::BusyError(_))
+    }
 }
 
 impl Error for VerifyPasswordError {}
@@ -68,6 +339,11 @@ impl Display for VerifyPasswordError {
         match self {
             VerifyPasswordError::ThreadError(e) => write!(f, "Thread error: {}", e),
             VerifyPasswordError::HashError(e) => write!(f, "Hash error: {}", e),
+            VerifyPasswordError::BcryptError(e) => write!(f, "Bcrypt error: {}", e),
+            VerifyPasswordError::UnknownPepperError => {
+                write!(f, "Hash was created with a pepper that isn't configured")
+            }
+            VerifyPasswordError::BusyError(e) => write!(f, "Busy error: {}", e),
         }
     }
 }
@@ -84,19 +360,266 @@ impl From<Argon2Error> for VerifyPasswordError {
     }
 }
 
+impl From<ParamsError> for VerifyPasswordError {
+    fn from(value: ParamsError) -> Self {
+        VerifyPasswordError::HashError(value.into())
+    }
+}
+
+impl From<BcryptError> for VerifyPasswordError {
+    fn from(value: BcryptError) -> Self {
+        VerifyPasswordError::BcryptError(value)
+    }
+}
+
+impl From<HashingBusyError> for VerifyPasswordError {
+    fn from(value: HashingBusyError) -> Self {
+        VerifyPasswordError::BusyError(value)
+    }
+}
+
+// Hashes imported from other systems, they are upgraded to argon2 on the next
+// sign in (`PasswordHashing::needs_rehash` is true for them).
+const SCRYPT_ALGORITHM: &str = "scrypt";
+const PBKDF2_SHA256_ALGORITHM: &str = "pbkdf2-sha256";
+const BCRYPT_PREFIXES: [&str; 3] = ["$2a$", "$2b$", "$2y$"];
+const BCRYPT_HASH_LENGTH: usize = 60;
+
+// bcrypt predates the PHC string format, so it's recognized by its prefix
+fn is_bcrypt_hash(hashed_password: &str) -> bool {
+    hashed_password.len() == BCRYPT_HASH_LENGTH
+        && BCRYPT_PREFIXES
+            .iter()
+            .any(|prefix| hashed_password.starts_with(prefix))
+}
+
+pub fn is_supported_hash(hashed_password: &str) -> bool {
+    if is_bcrypt_hash(hashed_password) {
+        return true;
+    }
+    let Ok(parsed_hash) = PasswordHash::new(hashed_password) else {
+        return false;
+    };
+    let algorithm = parsed_hash.algorithm.as_str();
+    Algorithm::new(algorithm).is_ok()
+        || algorithm == SCRYPT_ALGORITHM
+        || algorithm == PBKDF2_SHA256_ALGORITHM
+}
+
 pub async fn verify_password_in_separate_thread(
     password: String,
     hashed_password: String,
+    hashing: &PasswordHashing,
 ) -> Result<bool, VerifyPasswordError> {
-    let is_valid_password =
-        spawn_blocking(move || verify_password(&password.into_bytes(), &hashed_password)).await??;
+    let span = info_span!("verify_password");
+    let permit = hashing.limiter.acquire().await?;
+    let hashing = hashing.clone();
+    let is_valid_password = spawn_blocking(move || {
+        let _permit = permit;
+        span.in_scope(|| verify_password(&hashing, &password.into_bytes(), &hashed_password))
+    })
+    .await??;
     Ok(is_valid_password)
 }
 
-fn verify_password(password: &[u8], hashed_password: &str) -> Result<bool, Argon2Error> {
-    let parsed_hash = PasswordHash::new(hashed_password)?;
-    let is_valid_password = Argon2::default()
-        .verify_password(password, &parsed_hash)
-        .is_ok();
+fn verify_password(
+    hashing: &PasswordHashing,
+    password: &[u8],
+    hashed_password: &str,
+) -> Result<bool, VerifyPasswordError> {
+    let start = Instant::now();
+    let is_valid_password = if is_bcrypt_hash(hashed_password) {
+        bcrypt::verify(password, hashed_password)?
+    } else {
+        let parsed_hash = PasswordHash::new(hashed_password)?;
+        match parsed_hash.algorithm.as_str() {
+            SCRYPT_ALGORITHM => Scrypt.verify_password(password, &parsed_hash).is_ok(),
+            PBKDF2_SHA256_ALGORITHM => Pbkdf2.verify_password(password, &parsed_hash).is_ok(),
+            _ => match hashing.verifier(&parsed_hash) {
+                Ok(verifier) => verifier.verify_password(password, &parsed_hash).is_ok(),
+                // The pepper was removed from the config while hashes still
+                // use it, which the user can't fix, so it's only logged
+                Err(e @ VerifyPasswordError::UnknownPepperError) => {
+                    error!("Failed to verify password: {}", e);
+                    false
+                }
+                Err(e) => return Err(e),
+            },
+        }
+    };
+    record_password_hashing("verify", start.elapsed());
     Ok(is_valid_password)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        hash_password_in_separate_thread, is_supported_hash, verify_password_in_separate_thread,
+        HashingLimiter, PasswordHashing, Pepper, VerifyPasswordError,
+    };
+    use argon2::{
+        password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
+        Algorithm, Params,
+    };
+    use pbkdf2::{Params as Pbkdf2Params, Pbkdf2};
+    use scrypt::{Params as ScryptParams, Scrypt};
+    use std::sync::{atomic::Ordering, Arc};
+    use tokio::task::{spawn, yield_now};
+
+    fn create_weak_hashing() -> PasswordHashing {
+        PasswordHashing::new(Algorithm::Argon2i, Params::new(1024, 1, 1, None).unwrap())
+    }
+
+    #[tokio::test]
+    async fn detect_outdated_hash() {
+        let weak_hashing = create_weak_hashing();
+        let hashing = PasswordHashing::default();
+        let outdated_hash =
+            hash_password_in_separate_thread(String::from("password123"), &weak_hashing)
+                .await
+                .unwrap();
+        let current_hash = hash_password_in_separate_thread(String::from("password123"), &hashing)
+            .await
+            .unwrap();
+
+        assert!(hashing.needs_rehash(&outdated_hash));
+        assert!(!hashing.needs_rehash(&current_hash));
+        assert!(hashing.needs_rehash("not a hash"));
+    }
+
+    #[tokio::test]
+    async fn verify_hash_created_with_different_params() {
+        let hashed_password =
+            hash_password_in_separate_thread(String::from("password123"), &create_weak_hashing())
+                .await
+                .unwrap();
+
+        let is_valid = verify_password_in_separate_thread(
+            String::from("password123"),
+            hashed_password,
+            &PasswordHashing::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(is_valid);
+    }
+
+    #[tokio::test]
+    async fn verify_and_upgrade_legacy_hashes() {
+        let salt = SaltString::generate(&mut OsRng);
+        let legacy_hashes = [
+            bcrypt::hash("password123", 4).unwrap(),
+            Scrypt
+                .hash_password_customized(
+                    b"password123",
+                    None,
+                    None,
+                    ScryptParams::new(4, 8, 1, 32).unwrap(),
+                    &salt,
+                )
+                .unwrap()
+                .to_string(),
+            Pbkdf2
+                .hash_password_customized(
+                    b"password123",
+                    None,
+                    None,
+                    Pbkdf2Params {
+                        rounds: 1000,
+                        output_length: 32,
+                    },
+                    &salt,
+                )
+                .unwrap()
+                .to_string(),
+        ];
+        let hashing = PasswordHashing::default();
+
+        for hashed_password in legacy_hashes {
+            assert!(is_supported_hash(&hashed_password));
+            assert!(hashing.needs_rehash(&hashed_password));
+            assert!(verify_password_in_separate_thread(
+                String::from("password123"),
+                hashed_password.clone(),
+                &hashing,
+            )
+            .await
+            .unwrap());
+            assert!(!verify_password_in_separate_thread(
+                String::from("password124"),
+                hashed_password,
+                &hashing,
+            )
+            .await
+            .unwrap());
+        }
+    }
+
+    #[test]
+    fn reject_unsupported_hash() {
+        assert!(!is_supported_hash("password123"));
+        assert!(!is_supported_hash("$1$salt$hash"));
+        assert!(!is_supported_hash("$pbkdf2-sha512$i=1000$c2FsdA$aGFzaA"));
+    }
+
+    async fn verify(
+        hashing: &PasswordHashing,
+        hashed_password: &str,
+    ) -> Result<bool, VerifyPasswordError> {
+        verify_password_in_separate_thread(
+            String::from("password123"),
+            hashed_password.to_owned(),
+            hashing,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn verify_hash_after_pepper_rotation() {
+        let old_pepper = Pepper::new("1", vec![1; 32]).unwrap();
+        let new_pepper = Pepper::new("2", vec![2; 32]).unwrap();
+        let old_hashing = create_weak_hashing()
+            .with_peppers(vec![old_pepper.clone()])
+            .unwrap();
+        let rotated_hashing = create_weak_hashing()
+            .with_peppers(vec![new_pepper, old_pepper])
+            .unwrap();
+        let wrong_pepper_hashing = create_weak_hashing()
+            .with_peppers(vec![Pepper::new("1", vec![3; 32]).unwrap()])
+            .unwrap();
+        let hashed_password =
+            hash_password_in_separate_thread(String::from("password123"), &old_hashing)
+                .await
+                .unwrap();
+
+        assert!(hashed_password.contains("keyid="));
+        assert!(verify(&rotated_hashing, &hashed_password).await.unwrap());
+        assert!(rotated_hashing.needs_rehash(&hashed_password));
+        assert!(!old_hashing.needs_rehash(&hashed_password));
+        assert!(!verify(&wrong_pepper_hashing, &hashed_password)
+            .await
+            .unwrap());
+        assert!(!verify(&create_weak_hashing(), &hashed_password)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn reject_hashing_when_queue_is_full() {
+        let limiter = Arc::new(HashingLimiter::new(1, 1));
+        let permit = limiter.acquire().await.unwrap();
+        let queued = spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire().await.is_ok() }
+        });
+        while limiter.queue_length.load(Ordering::SeqCst) == 0 {
+            yield_now().await;
+        }
+
+        assert!(limiter.acquire().await.is_err());
+        drop(permit);
+        assert!(queued.await.unwrap());
+        assert_eq!(limiter.queue_length.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,157 @@
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use zxcvbn::zxcvbn;
+
+pub const MAX_SCORE: u8 = 4;
+
+// Only passwords passing the length limits are checked, so the list doesn't
+// need entries shorter than the minimum length.
+static COMMON_PASSWORDS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    include_str!("../../data/common-passwords.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect()
+});
+
+// The estimation gets slow for long inputs and they are strong anyway, so
+// only the beginning is scored (the same limit as the zxcvbn JS library).
+const MAX_ESTIMATED_LENGTH: usize = 100;
+// Shorter local parts (e.g. `a@example.com`) would reject too many passwords
+const MIN_EMAIL_LOCAL_PART_LENGTH: usize = 3;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PasswordPolicy {
+    pub min_score: u8,
+    pub reject_common: bool,
+    pub reject_email: bool,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_score: 3,
+            reject_common: true,
+            reject_email: true,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PasswordPolicyViolation {
+    CommonPassword,
+    ContainsEmail,
+    TooWeak,
+}
+
+impl PasswordPolicy {
+    pub fn check(&self, password: &str, email: &str) -> Result<(), PasswordPolicyViolation> {
+        let password_lowercase = password.to_lowercase();
+        if self.reject_common && COMMON_PASSWORDS.contains(password_lowercase.as_str()) {
+            return Err(PasswordPolicyViolation::CommonPassword);
+        }
+        let local_part = get_email_local_part(email).to_lowercase();
+        if self.reject_email
+            && local_part.chars().count() >= MIN_EMAIL_LOCAL_PART_LENGTH
+            && password_lowercase.contains(&local_part)
+        {
+            return Err(PasswordPolicyViolation::ContainsEmail);
+        }
+        if estimate_password_strength(password, email).score < self.min_score {
+            return Err(PasswordPolicyViolation::TooWeak);
+        }
+        Ok(())
+    }
+}
+
+pub struct PasswordStrength {
+    // From 0 (guessable within a few attempts) to `MAX_SCORE`
+    pub score: u8,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+pub fn estimate_password_strength(password: &str, email: &str) -> PasswordStrength {
+    let password: String = password.chars().take(MAX_ESTIMATED_LENGTH).collect();
+    let user_inputs = [email, get_email_local_part(email)];
+    // Only an empty password is rejected by the estimator
+    let Ok(entropy) = zxcvbn(&password, &user_inputs) else {
+        return PasswordStrength {
+            score: 0,
+            warning: None,
+            suggestions: Vec::new(),
+        };
+    };
+    let feedback = entropy.feedback().as_ref();
+    PasswordStrength {
+        score: entropy.score(),
+        warning: feedback
+            .and_then(|feedback| feedback.warning())
+            .map(|warning| warning.to_string()),
+        suggestions: feedback
+            .map(|feedback| {
+                feedback
+                    .suggestions()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+fn get_email_local_part(email: &str) -> &str {
+    email.split('@').next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_password_strength, PasswordPolicy, PasswordPolicyViolation};
+
+    #[test]
+    fn reject_common_password() {
+        let policy = PasswordPolicy::default();
+
+        assert_eq!(
+            policy.check("Password123", "test@example.com"),
+            Err(PasswordPolicyViolation::CommonPassword)
+        );
+    }
+
+    #[test]
+    fn reject_password_containing_email() {
+        let policy = PasswordPolicy::default();
+
+        assert_eq!(
+            policy.check("john.smith-Vx93!qLm", "John.Smith@example.com"),
+            Err(PasswordPolicyViolation::ContainsEmail)
+        );
+    }
+
+    #[test]
+    fn reject_weak_password() {
+        let policy = PasswordPolicy::default();
+
+        assert_eq!(
+            policy.check("abcabcabc", "test@example.com"),
+            Err(PasswordPolicyViolation::TooWeak)
+        );
+    }
+
+    #[test]
+    fn accept_strong_password() {
+        let policy = PasswordPolicy::default();
+
+        assert!(policy
+            .check("correct horse battery staple", "test@example.com")
+            .is_ok());
+    }
+
+    #[test]
+    fn give_feedback_for_weak_password() {
+        let strength = estimate_password_strength("abcabcabc", "test@example.com");
+
+        assert!(strength.score < 3);
+        assert!(strength.warning.is_some() || !strength.suggestions.is_empty());
+    }
+}
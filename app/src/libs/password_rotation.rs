@@ -0,0 +1,66 @@
+use crate::libs::recent_auth::redirect_with_return_path;
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tower_sessions::{session::Error as SessionError, Session};
+use tracing::error;
+
+const PASSWORD_CHANGE_REQUIRED_KEY: &str = "auth.password_change_required";
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PasswordRotationPolicy {
+    // Number of previous passwords which can't be reused, the current
+    // password is always rejected
+    pub history_size: u32,
+    // Passwords never expire when 0
+    pub max_age_days: u16,
+}
+
+#[derive(Clone, Copy)]
+pub struct PasswordChangeRedirect {
+    pub route: &'static str,
+    // Where htmx swaps the change password page
+    pub target: &'static str,
+}
+
+// Set after sign-in when the password expired or an admin required a change,
+// so the check doesn't hit the database on every request.
+pub async fn mark_password_change_required(
+    session: &Session,
+    required: bool,
+) -> Result<(), SessionError> {
+    if required {
+        session.insert(PASSWORD_CHANGE_REQUIRED_KEY, true).await
+    } else {
+        session
+            .remove::<bool>(PASSWORD_CHANGE_REQUIRED_KEY)
+            .await
+            .map(|_| ())
+    }
+}
+
+pub async fn is_password_change_pending(session: &Session) -> Result<bool, SessionError> {
+    let required = session.get::<bool>(PASSWORD_CHANGE_REQUIRED_KEY).await?;
+    Ok(required.unwrap_or(false))
+}
+
+// Keeps the user on the change password page until the password is changed.
+// Meant to be layered on top of `login_required!`.
+pub async fn enforce_password_change(
+    State(redirect): State<PasswordChangeRedirect>,
+    session: Session,
+    request: Request,
+    next: Next,
+) -> Response {
+    match is_password_change_pending(&session).await {
+        Ok(false) => next.run(request).await,
+        Ok(true) => redirect_with_return_path(&request, redirect.route, redirect.target),
+        Err(e) => {
+            error!("Failed to read pending password change: {:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    max_requests: u32,
+    window: Duration,
+    windows: Arc<Mutex<HashMap<String, Window>>>,
+}
+
+struct Window {
+    started_at: Instant,
+    requests: u32,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+            windows: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    // Fixed window counter per key, good enough for a single instance deployment.
+    pub fn try_acquire(&self, key: &str) -> bool {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().expect("Rate limiter lock is poisoned");
+        windows.retain(|_, window| now.duration_since(window.started_at) < self.window);
+        let window = windows.entry(key.to_owned()).or_insert(Window {
+            started_at: now,
+            requests: 0,
+        });
+        if window.requests >= self.max_requests {
+            return false;
+        }
+        window.requests += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimiter;
+    use std::time::Duration;
+
+    #[test]
+    fn requests_above_limit_are_rejected() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+
+        assert!(limiter.try_acquire("key"));
+        assert!(limiter.try_acquire("key"));
+        assert!(!limiter.try_acquire("key"));
+    }
+
+    #[test]
+    fn keys_are_limited_separately() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(60));
+
+        assert!(limiter.try_acquire("first"));
+        assert!(limiter.try_acquire("second"));
+    }
+
+    #[test]
+    fn limit_is_reset_after_window() {
+        let limiter = RateLimiter::new(1, Duration::ZERO);
+
+        assert!(limiter.try_acquire("key"));
+        assert!(limiter.try_acquire("key"));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::api::htmx::{HtmxLocation, HtmxRequest, HtmxResponse};
+use axum::{
+    extract::{Request, State},
+    http::{Method, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use time::{Duration, OffsetDateTime};
+use tower_sessions::{session::Error as SessionError, Session};
+use tracing::error;
+use urlencoding::encode;
+
+const LAST_STRONG_AUTH_KEY: &str = "auth.last_strong_auth_at";
+
+#[derive(Clone, Copy)]
+pub struct RecentAuthPolicy {
+    pub max_age: Duration,
+    pub reauthenticate_route: &'static str,
+    // Where htmx swaps the re-authentication page
+    pub target: &'static str,
+}
+
+// Called whenever the user proves their identity, e.g. by entering the password
+pub async fn record_strong_auth(session: &Session) -> Result<(), SessionError> {
+    session
+        .insert(
+            LAST_STRONG_AUTH_KEY,
+            OffsetDateTime::now_utc().unix_timestamp(),
+        )
+        .await
+}
+
+pub async fn has_recent_auth(session: &Session, max_age: Duration) -> Result<bool, SessionError> {
+    let last_strong_auth_at = session.get::<i64>(LAST_STRONG_AUTH_KEY).await?;
+    Ok(is_recent(
+        last_strong_auth_at,
+        max_age,
+        OffsetDateTime::now_utc(),
+    ))
+}
+
+fn is_recent(last_strong_auth_at: Option<i64>, max_age: Duration, now: OffsetDateTime) -> bool {
+    last_strong_auth_at
+        .and_then(|timestamp| OffsetDateTime::from_unix_timestamp(timestamp).ok())
+        .map_or(false, |last_strong_auth_at| {
+            now - last_strong_auth_at <= max_age
+        })
+}
+
+// Sends the user to the re-authentication page, which returns them to the
+// requested page afterwards. Meant to be layered on top of `login_required!`.
+pub async fn require_recent_auth(
+    State(policy): State<RecentAuthPolicy>,
+    session: Session,
+    request: Request,
+    next: Next,
+) -> Response {
+    match has_recent_auth(&session, policy.max_age).await {
+        Ok(true) => return next.run(request).await,
+        Ok(false) => {}
+        Err(e) => {
+            error!("Failed to read last strong authentication: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+    redirect_with_return_path(&request, policy.reauthenticate_route, policy.target)
+}
+
+// Sends the user to an interstitial page, which returns them to the
+// requested page afterwards.
+pub(crate) fn redirect_with_return_path(request: &Request, route: &str, target: &str) -> Response {
+    let htmx_request = HtmxRequest::from_headers(request.headers());
+    let next_path = get_return_path(request, &htmx_request);
+    let url = format!("{}?next={}", route, encode(&next_path));
+    if htmx_request.expects_fragment() {
+        let location = HtmxLocation::new(&url).target(target);
+        return (StatusCode::OK, HtmxResponse::new().location(location)).into_response();
+    }
+    Redirect::to(&url).into_response()
+}
+
+// Other methods can't be repeated by a redirect, so the user is returned to
+// the page the action was triggered from.
+fn get_return_path(request: &Request, htmx_request: &HtmxRequest) -> String {
+    let uri = if request.method() == Method::GET {
+        Some(request.uri().clone())
+    } else {
+        htmx_request
+            .current_url
+            .as_deref()
+            .and_then(|url| url.parse::<Uri>().ok())
+    };
+    uri.and_then(|uri| uri.path_and_query().map(|path| path.as_str().to_owned()))
+        .unwrap_or_else(|| String::from("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_return_path, is_recent};
+    use crate::api::htmx::HtmxRequest;
+    use axum::{body::Body, extract::Request, http::Method};
+    use time::{Duration, OffsetDateTime};
+
+    #[test]
+    fn check_recent_auth() {
+        let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let max_age = Duration::minutes(10);
+
+        assert!(is_recent(Some(1_700_000_000 - 60), max_age, now));
+        assert!(!is_recent(Some(1_700_000_000 - 660), max_age, now));
+        assert!(!is_recent(None, max_age, now));
+    }
+
+    #[test]
+    fn return_to_page_of_action() {
+        let get_request = Request::builder()
+            .uri("/account?tab=email")
+            .body(Body::empty())
+            .unwrap();
+        let post_request = Request::builder()
+            .method(Method::POST)
+            .uri("/account/delete")
+            .body(Body::empty())
+            .unwrap();
+        let htmx_request = HtmxRequest {
+            current_url: Some(String::from("http://localhost:3000/account")),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            get_return_path(&get_request, &HtmxRequest::default()),
+            "/account?tab=email"
+        );
+        assert_eq!(get_return_path(&post_request, &htmx_request), "/account");
+        assert_eq!(get_return_path(&post_request, &HtmxRequest::default()), "/");
+    }
+}
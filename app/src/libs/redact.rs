@@ -0,0 +1,193 @@
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex, Replacer};
+use std::{
+    borrow::Cow,
+    fmt::{Debug as FormatDebug, Display, Formatter, Result as FormatResult, Write},
+};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::{
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields},
+    registry::LookupSpan,
+};
+
+const REDACTED: &str = "[REDACTED]";
+
+// The values may be quoted, or quoted with escaped quotes within a JSON
+// string, and bare values stop at quotes, so JSON lines stay valid.
+static SECRET_VALUE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)\b(password|secret|token|api[_-]?key|authorization)(\\?["']?\s*[:=]\s*)(\\"(?:[^"\\]|\\[^"])*\\"|"(?:[^"\\]|\\.)*"|[^\s,;&}"\\]+)"#)
+        .expect("Wrong regex pattern")
+});
+static COOKIE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)\b(cookie|set-cookie)(\\?["']?\s*[:=]\s*)(\\"(?:[^"\\]|\\[^"])*\\"|"(?:[^"\\]|\\.)*"|[^\n"\\]*)"#)
+        .expect("Wrong regex pattern")
+});
+static BEARER_TOKEN_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\bbearer\s+[a-zA-Z0-9._~+/=-]+").expect("Wrong regex pattern"));
+// Every hash format accepted when importing users, see `libs::password`
+static PASSWORD_HASH_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"\$(?:argon2(?:id|i|d)|2[aby]|scrypt|pbkdf2-sha256)\$[^\s"\\]+"#)
+        .expect("Wrong regex pattern")
+});
+static EMAIL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"[a-zA-Z0-9.!#$%&'*+/=?^_`{|}~-]+@[a-zA-Z0-9-]+(?:\.[a-zA-Z0-9-]+)*")
+        .expect("Wrong regex pattern")
+});
+
+// Wraps a value which must never be logged, e.g. `debug!("{}", Redacted(&email))`
+pub struct Redacted<T>(pub T);
+
+impl<T> FormatDebug for Redacted<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        write!(f, "{}", REDACTED)
+    }
+}
+
+impl<T> Display for Redacted<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        write!(f, "{}", REDACTED)
+    }
+}
+
+// Last line of defence for the values which slip through without the
+// `Redacted` wrapper, e.g. inside database errors.
+pub fn redact(text: &str) -> Cow<str> {
+    let mut text = Cow::Borrowed(text);
+    replace_all(&mut text, &SECRET_VALUE_REGEX, redact_value);
+    replace_all(&mut text, &COOKIE_REGEX, redact_value);
+    replace_all(&mut text, &BEARER_TOKEN_REGEX, "Bearer [REDACTED]");
+    replace_all(&mut text, &PASSWORD_HASH_REGEX, REDACTED);
+    replace_all(&mut text, &EMAIL_REGEX, REDACTED);
+    text
+}
+
+fn replace_all(text: &mut Cow<str>, regex: &Regex, replacement: impl Replacer) {
+    let replaced = match regex.replace_all(&**text, replacement) {
+        Cow::Borrowed(_) => None,
+        Cow::Owned(replaced) => Some(replaced),
+    };
+    if let Some(replaced) = replaced {
+        *text = Cow::Owned(replaced);
+    }
+}
+
+// Keeps the quotes around the value, if any
+fn redact_value(captures: &Captures) -> String {
+    let value = &captures[3];
+    let quote = if value.starts_with(r#"\""#) {
+        r#"\""#
+    } else if value.starts_with('"') {
+        "\""
+    } else {
+        ""
+    };
+    format!(
+        "{}{}{}{}{}",
+        &captures[1], &captures[2], quote, REDACTED, quote
+    )
+}
+
+// Formats the whole event first, so the values which are split across the
+// fields are redacted the same way regardless of the writer.
+pub struct RedactingFormat<F> {
+    format: F,
+}
+
+impl<F> RedactingFormat<F> {
+    pub fn new(format: F) -> Self {
+        Self { format }
+    }
+}
+
+impl<S, N, F> FormatEvent<S, N> for RedactingFormat<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> FormatResult {
+        let mut buffer = String::new();
+        self.format
+            .format_event(ctx, Writer::new(&mut buffer), event)?;
+        writer.write_str(&redact(&buffer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redact, Redacted};
+
+    #[test]
+    fn redacted_value_is_not_logged() {
+        let log = format!(
+            "{} {:?}",
+            Redacted("test@example.com"),
+            Redacted("password123")
+        );
+
+        assert_eq!(log, "[REDACTED] [REDACTED]");
+    }
+
+    #[test]
+    fn redact_emails() {
+        let log = redact("User with email test@example.com not found");
+
+        assert_eq!(log, "User with email [REDACTED] not found");
+    }
+
+    #[test]
+    fn redact_secrets() {
+        let log = redact(r#"password=password123 Credentials { token: "abc" } api_key: xyz"#);
+
+        assert_eq!(
+            log,
+            r#"password=[REDACTED] Credentials { token: "[REDACTED]" } api_key: [REDACTED]"#
+        );
+    }
+
+    #[test]
+    fn keep_json_lines_valid() {
+        let log = redact(
+            r#"{"message":"password=abc token=\"xyz\"","password":"x","cookie":"id=secret; theme=dark","target":"app"}"#,
+        );
+
+        assert_eq!(
+            log,
+            r#"{"message":"password=[REDACTED] token=\"[REDACTED]\"","password":"[REDACTED]","cookie":"[REDACTED]","target":"app"}"#
+        );
+    }
+
+    #[test]
+    fn redact_tokens_and_cookies() {
+        let log = redact(r#"{"authorization": "Bearer abc.def", "cookie": "id=secret"}"#);
+
+        assert!(!log.contains("abc.def"));
+        assert!(!log.contains("id=secret"));
+    }
+
+    #[test]
+    fn redact_password_hashes() {
+        for hash in [
+            "$argon2id$v=19$m=19456,t=2,p=1$c2FsdA$aGFzaA",
+            "$2b$12$R9h/cIPz0gi.URNNX3kh2OPST9/PgBkqquzi.Ss7KIUgO2t0jWMUW",
+            "$scrypt$ln=15,r=8,p=1$c2FsdA$aGFzaA",
+            "$pbkdf2-sha256$i=600000,l=32$c2FsdA$aGFzaA",
+        ] {
+            let log = redact(&format!("hash {}", hash));
+
+            assert_eq!(log, "hash [REDACTED]");
+        }
+    }
+
+    #[test]
+    fn keep_logs_without_secrets() {
+        let log = redact("Finished processing request");
+
+        assert_eq!(log, "Finished processing request");
+    }
+}
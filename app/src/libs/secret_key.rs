@@ -0,0 +1,223 @@
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine as _,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::Arc;
+use tower_sessions::cookie::{Cookie, CookieJar, Key};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Signing keys shorter than that are rejected by the cookie crate
+pub const MIN_SECRET_KEY_LENGTH: usize = 64;
+
+#[derive(Clone, Copy)]
+pub enum CookieProtection {
+    // Readable by the client, but can't be tampered with
+    Signed,
+    // Encrypted, so the value is also hidden from the client
+    Private,
+}
+
+pub fn generate_secret_key() -> String {
+    STANDARD.encode(Key::generate().master())
+}
+
+// The first key signs new cookies, the rest only verify the existing ones, so
+// a new key can be added in front without logging everybody out.
+#[derive(Clone)]
+pub struct SecretKeys {
+    keys: Arc<Vec<Key>>,
+}
+
+impl SecretKeys {
+    pub fn new(keys: &[Vec<u8>]) -> Self {
+        assert!(!keys.is_empty(), "At least one secret key is required");
+        Self {
+            keys: Arc::new(keys.iter().map(|key| Key::from(key)).collect()),
+        }
+    }
+
+    pub fn signing_key(&self) -> &Key {
+        &self.keys[0]
+    }
+
+    // Returns the cookie header with the given cookie protected by the signing
+    // key, if it was protected by one of the previous keys.
+    pub fn resign_cookie(
+        &self,
+        cookie_header: &str,
+        name: &str,
+        protection: CookieProtection,
+    ) -> Option<String> {
+        let cookies: Vec<_> = Cookie::split_parse(cookie_header)
+            .filter_map(Result::ok)
+            .collect();
+        let mut jar = CookieJar::new();
+        for cookie in &cookies {
+            jar.add_original(cookie.clone());
+        }
+        let verify = |jar: &CookieJar, key: &Key| match protection {
+            CookieProtection::Signed => jar.signed(key).get(name),
+            CookieProtection::Private => jar.private(key).get(name),
+        };
+        if jar.get(name).is_none() || verify(&jar, self.signing_key()).is_some() {
+            return None;
+        }
+        let verified = self.keys[1..].iter().find_map(|key| verify(&jar, key))?;
+        match protection {
+            CookieProtection::Signed => jar.signed_mut(self.signing_key()).add(verified),
+            CookieProtection::Private => jar.private_mut(self.signing_key()).add(verified),
+        }
+        let resigned = jar.get(name)?;
+        let header = cookies
+            .iter()
+            .map(|cookie| {
+                let value = if cookie.name() == name {
+                    resigned.value()
+                } else {
+                    cookie.value()
+                };
+                format!("{}={}", cookie.name(), value)
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        Some(header)
+    }
+
+    // Signs values passed outside of cookies (e.g. in download links), the
+    // signature is URL safe. Each purpose signs with its own key derived from
+    // the secret key, so a signature is only valid for the purpose it was made
+    // for and never matches one made by the cookie key.
+    pub fn sign(&self, purpose: &str, message: &str) -> String {
+        let mac = create_mac(self.signing_key(), purpose, message);
+        URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    }
+
+    pub fn verify(&self, purpose: &str, message: &str, signature: &str) -> bool {
+        let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+            return false;
+        };
+        self.keys.iter().any(|key| {
+            create_mac(key, purpose, message)
+                .verify_slice(&signature)
+                .is_ok()
+        })
+    }
+}
+
+fn create_hmac(key: &[u8], message: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac
+}
+
+fn create_mac(key: &Key, purpose: &str, message: &str) -> HmacSha256 {
+    let purpose_key = create_hmac(key.master(), purpose.as_bytes())
+        .finalize()
+        .into_bytes();
+    create_hmac(&purpose_key, message.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{create_hmac, CookieProtection, SecretKeys};
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    use tower_sessions::cookie::{Cookie, CookieJar};
+
+    fn sign_cookie(keys: &SecretKeys, name: &str, value: &str) -> String {
+        let mut jar = CookieJar::new();
+        jar.signed_mut(keys.signing_key())
+            .add(Cookie::new(name.to_owned(), value.to_owned()));
+        jar.get(name).unwrap().value().to_owned()
+    }
+
+    #[test]
+    fn resign_cookie_signed_by_previous_key() {
+        let old_key = vec![1; 64];
+        let new_key = vec![2; 64];
+        let old_keys = SecretKeys::new(&[old_key.clone()]);
+        let keys = SecretKeys::new(&[new_key, old_key]);
+        let header = format!("theme=dark; id={}", sign_cookie(&old_keys, "id", "session"));
+
+        let resigned = keys
+            .resign_cookie(&header, "id", CookieProtection::Signed)
+            .unwrap();
+
+        assert_eq!(
+            resigned,
+            format!("theme=dark; id={}", sign_cookie(&keys, "id", "session"))
+        );
+    }
+
+    #[test]
+    fn keep_cookie_signed_by_current_key() {
+        let keys = SecretKeys::new(&[vec![1; 64], vec![2; 64]]);
+        let header = format!("id={}", sign_cookie(&keys, "id", "session"));
+
+        assert!(keys
+            .resign_cookie(&header, "id", CookieProtection::Signed)
+            .is_none());
+    }
+
+    #[test]
+    fn ignore_cookie_with_unknown_signature() {
+        let keys = SecretKeys::new(&[vec![1; 64], vec![2; 64]]);
+        let unknown_keys = SecretKeys::new(&[vec![3; 64]]);
+        let header = format!("id={}", sign_cookie(&unknown_keys, "id", "session"));
+
+        assert!(keys
+            .resign_cookie(&header, "id", CookieProtection::Signed)
+            .is_none());
+        assert!(keys
+            .resign_cookie("theme=dark", "id", CookieProtection::Signed)
+            .is_none());
+    }
+
+    #[test]
+    fn reencrypt_private_cookie() {
+        let old_key = vec![1; 64];
+        let keys = SecretKeys::new(&[vec![2; 64], old_key.clone()]);
+        let old_keys = SecretKeys::new(&[old_key]);
+        let mut jar = CookieJar::new();
+        jar.private_mut(old_keys.signing_key())
+            .add(Cookie::new("id", "session"));
+        let header = format!("id={}", jar.get("id").unwrap().value());
+
+        let resigned = keys
+            .resign_cookie(&header, "id", CookieProtection::Private)
+            .unwrap();
+
+        let mut jar = CookieJar::new();
+        for cookie in Cookie::split_parse(resigned).filter_map(Result::ok) {
+            jar.add_original(cookie);
+        }
+        let cookie = jar.private(keys.signing_key()).get("id").unwrap();
+        assert_eq!(cookie.value(), "session");
+    }
+
+    #[test]
+    fn verify_signature_of_current_and_previous_keys() {
+        let old_key = vec![1; 64];
+        let old_keys = SecretKeys::new(&[old_key.clone()]);
+        let keys = SecretKeys::new(&[vec![2; 64], old_key]);
+        let unknown_keys = SecretKeys::new(&[vec![3; 64]]);
+
+        assert!(keys.verify("test", "message", &keys.sign("test", "message")));
+        assert!(keys.verify("test", "message", &old_keys.sign("test", "message")));
+        assert!(!keys.verify("test", "message", &unknown_keys.sign("test", "message")));
+        assert!(!keys.verify("test", "other message", &keys.sign("test", "message")));
+        assert!(!keys.verify("test", "message", "not base64!"));
+    }
+
+    #[test]
+    fn verify_signature_of_same_purpose_only() {
+        let keys = SecretKeys::new(&[vec![1; 64]]);
+        let cookie_mac = create_hmac(keys.signing_key().signing(), b"message");
+        let cookie_signature = URL_SAFE_NO_PAD.encode(cookie_mac.finalize().into_bytes());
+
+        assert!(!keys.verify("test", "message", &keys.sign("other", "message")));
+        assert!(!keys.verify("test", "message", &cookie_signature));
+    }
+}
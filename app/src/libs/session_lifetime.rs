@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use tower_sessions::{session::Error as SessionError, Session};
+
+const SESSION_LIFETIME_KEY: &str = "auth.lifetime";
+
+#[derive(Clone, Copy)]
+pub struct SessionPolicy {
+    pub idle_timeout: Duration,
+    pub absolute_lifetime: Duration,
+}
+
+#[derive(Clone, Copy)]
+pub struct SessionPolicies {
+    pub default: SessionPolicy,
+    pub remember_me: SessionPolicy,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionLifetime {
+    authenticated_at: i64,
+    remember_me: bool,
+}
+
+impl SessionLifetime {
+    pub fn new(authenticated_at: OffsetDateTime, remember_me: bool) -> Self {
+        Self {
+            authenticated_at: authenticated_at.unix_timestamp(),
+            remember_me,
+        }
+    }
+
+    // The session expires after being idle, but never later than the absolute
+    // lifetime counted from the authentication. `None` means it has expired.
+    pub fn expires_at(
+        &self,
+        policies: &SessionPolicies,
+        now: OffsetDateTime,
+    ) -> Option<OffsetDateTime> {
+        let policy = if self.remember_me {
+            policies.remember_me
+        } else {
+            policies.default
+        };
+        let authenticated_at = OffsetDateTime::from_unix_timestamp(self.authenticated_at).ok()?;
+        let absolute_expiry = authenticated_at + policy.absolute_lifetime;
+        if now >= absolute_expiry {
+            return None;
+        }
+        Some(absolute_expiry.min(now + policy.idle_timeout))
+    }
+}
+
+pub async fn start_session_lifetime(
+    session: &Session,
+    remember_me: bool,
+) -> Result<(), SessionError> {
+    let lifetime = SessionLifetime::new(OffsetDateTime::now_utc(), remember_me);
+    session.insert(SESSION_LIFETIME_KEY, lifetime).await
+}
+
+pub async fn read_session_lifetime(
+    session: &Session,
+) -> Result<Option<SessionLifetime>, SessionError> {
+    session.get(SESSION_LIFETIME_KEY).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SessionLifetime, SessionPolicies, SessionPolicy};
+    use time::{Duration, OffsetDateTime};
+
+    const POLICIES: SessionPolicies = SessionPolicies {
+        default: SessionPolicy {
+            idle_timeout: Duration::minutes(30),
+            absolute_lifetime: Duration::hours(12),
+        },
+        remember_me: SessionPolicy {
+            idle_timeout: Duration::days(7),
+            absolute_lifetime: Duration::days(30),
+        },
+    };
+
+    #[test]
+    fn extend_session_by_idle_timeout() {
+        let authenticated_at = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let now = authenticated_at + Duration::hours(1);
+        let lifetime = SessionLifetime::new(authenticated_at, false);
+
+        let expires_at = lifetime.expires_at(&POLICIES, now);
+
+        assert_eq!(expires_at, Some(now + Duration::minutes(30)));
+    }
+
+    #[test]
+    fn limit_session_by_absolute_lifetime() {
+        let authenticated_at = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let now = authenticated_at + Duration::days(29);
+        let lifetime = SessionLifetime::new(authenticated_at, true);
+
+        let expires_at = lifetime.expires_at(&POLICIES, now);
+
+        assert_eq!(expires_at, Some(authenticated_at + Duration::days(30)));
+    }
+
+    #[test]
+    fn expire_session_after_absolute_lifetime() {
+        let authenticated_at = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let lifetime = SessionLifetime::new(authenticated_at, false);
+
+        assert!(lifetime
+            .expires_at(&POLICIES, authenticated_at + Duration::hours(12))
+            .is_none());
+        assert!(lifetime
+            .expires_at(&POLICIES, authenticated_at + Duration::hours(11))
+            .is_some());
+    }
+}
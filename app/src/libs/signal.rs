@@ -1,12 +1,19 @@
+use crate::libs::health::Readiness;
 use tokio::{
     signal::{
         ctrl_c,
         unix::{signal, SignalKind},
     },
     task::AbortHandle,
+    time::{sleep, Duration},
 };
+use tracing::info;
 
-pub async fn shutdown_signal(abort_handler: AbortHandle) {
+pub async fn shutdown_signal(
+    abort_handles: Vec<AbortHandle>,
+    readiness: Readiness,
+    readiness_delay: Duration,
+) {
     let ctrl_c = async {
         ctrl_c().await.expect("Failed to install Ctrl+C handler");
     };
@@ -23,7 +30,17 @@ pub async fn shutdown_signal(abort_handler: AbortHandle) {
     let terminate = std::future::pending::<()>();
 
     tokio::select! {
-        _ = ctrl_c => { abort_handler.abort() },
-        _ = terminate => { abort_handler.abort() },
+        _ = ctrl_c => {},
+        _ = terminate => {},
     }
+    for abort_handle in abort_handles {
+        abort_handle.abort();
+    }
+
+    readiness.set_shutting_down();
+    info!(
+        "Shutting down, reporting not ready for {:?} before draining connections",
+        readiness_delay
+    );
+    sleep(readiness_delay).await;
 }
@@ -1,5 +1,7 @@
+use axum::http::Uri;
 use once_cell::sync::Lazy;
 use regex::Regex;
+use urlencoding::decode;
 
 // Regex from the specs: https://html.spec.whatwg.org/multipage/forms.html#valid-e-mail-address
 static EMAIL_REGEX: Lazy<Regex> = Lazy::new(|| {
@@ -10,9 +12,30 @@ pub fn is_valid_email(email: &str) -> bool {
     EMAIL_REGEX.is_match(email)
 }
 
+// Only paths on the same site are allowed as redirect targets, so links with
+// e.g. `?next=https://attacker.com` can't send users elsewhere. Browsers strip
+// tabs and newlines from URLs, which would turn `/\t/attacker.com` into
+// `//attacker.com`, the decoded path is checked too in case it's decoded again.
+pub fn is_local_path(path: &str) -> bool {
+    let is_raw_path_local = !path.chars().any(char::is_whitespace)
+        && is_path_without_host(path)
+        && path
+            .parse::<Uri>()
+            .is_ok_and(|uri| uri.scheme().is_none() && uri.authority().is_none());
+    is_raw_path_local && decode(path).is_ok_and(|decoded| is_path_without_host(&decoded))
+}
+
+fn is_path_without_host(path: &str) -> bool {
+    path.starts_with('/')
+        && !path.starts_with("//")
+        && !path
+            .chars()
+            .any(|character| character == '\\' || character.is_control())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::is_valid_email;
+    use super::{is_local_path, is_valid_email};
 
     #[test]
     fn email_is_valid() {
@@ -23,4 +46,22 @@ mod tests {
     fn email_is_invalid() {
         assert!(!is_valid_email("test"));
     }
+
+    #[test]
+    fn path_is_local() {
+        assert!(is_local_path("/protected?tab=1"));
+        assert!(!is_local_path("https://example.com"));
+        assert!(!is_local_path("//example.com"));
+        assert!(!is_local_path("/\\example.com"));
+    }
+
+    #[test]
+    fn path_with_control_characters_is_not_local() {
+        assert!(!is_local_path("/\t/example.com"));
+        assert!(!is_local_path("/\r/example.com"));
+        assert!(!is_local_path("/\n/example.com"));
+        assert!(!is_local_path("/%09/example.com"));
+        assert!(!is_local_path("/%2F/example.com"));
+        assert!(!is_local_path("/ /example.com"));
+    }
 }
@@ -1,8 +1,54 @@
-use app::server::run_server;
+use app::{
+    cli::{Cli, Command},
+    config::LoadedConfig,
+    import::run_import_users,
+    libs::secret_key::generate_secret_key,
+    server::run_server,
+};
+use clap::Parser;
 use dotenv::dotenv;
+use std::process::exit;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    run_server().await;
+    let cli = Cli::parse();
+    if let Some(Command::GenerateKey) = &cli.command {
+        println!("{}", generate_secret_key());
+        return;
+    }
+    let loaded_config = LoadedConfig::load(cli.config_sources());
+    if cli.print_config {
+        print!("{}", loaded_config.to_redacted_string());
+    }
+    let config = match loaded_config.validate() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+    if cli.print_config {
+        return;
+    }
+    if let Some(Command::ImportUsers { path }) = &cli.command {
+        match run_import_users(config, path).await {
+            Ok(summary) => {
+                for skipped in &summary.skipped {
+                    eprintln!("Skipped {}", skipped);
+                }
+                println!(
+                    "Imported {} users, skipped {}",
+                    summary.imported,
+                    summary.skipped.len()
+                );
+            }
+            Err(e) => {
+                eprintln!("Failed to import users: {}", e);
+                exit(1);
+            }
+        }
+        return;
+    }
+    run_server(config).await;
 }
@@ -0,0 +1,72 @@
+use crate::db::connection::Database;
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use once_cell::sync::OnceCell;
+use std::time::Duration;
+
+const DURATION_BUCKETS_SECONDS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static METRICS_HANDLE: OnceCell<PrometheusHandle> = OnceCell::new();
+
+// The recorder is global, so it's installed once and shared by all routers
+pub fn setup_metrics() -> PrometheusHandle {
+    METRICS_HANDLE
+        .get_or_init(|| {
+            PrometheusBuilder::new()
+                .set_buckets_for_metric(
+                    Matcher::Suffix(String::from("duration_seconds")),
+                    DURATION_BUCKETS_SECONDS,
+                )
+                .expect("Invalid metric buckets")
+                .install_recorder()
+                .expect("Failed to install metrics recorder")
+        })
+        .clone()
+}
+
+pub fn record_http_request(method: &str, route: &str, status: u16, duration: Duration) {
+    let labels = [
+        ("method", method.to_owned()),
+        ("route", route.to_owned()),
+        ("status", status.to_string()),
+    ];
+    counter!("http_requests_total", &labels).increment(1);
+    histogram!("http_request_duration_seconds", &labels).record(duration.as_secs_f64());
+}
+
+pub fn record_auth_event(event: &'static str, outcome: &'static str) {
+    counter!("auth_events_total", "event" => event, "outcome" => outcome).increment(1);
+}
+
+pub fn record_password_hashing(operation: &'static str, duration: Duration) {
+    histogram!("password_hashing_duration_seconds", "operation" => operation)
+        .record(duration.as_secs_f64());
+}
+
+pub fn record_password_hashing_wait(duration: Duration) {
+    histogram!("password_hashing_wait_duration_seconds").record(duration.as_secs_f64());
+}
+
+pub fn record_password_hashing_rejection() {
+    counter!("password_hashing_rejected_total").increment(1);
+}
+
+pub fn record_password_breach_check_failure() {
+    counter!("password_breach_check_failures_total").increment(1);
+}
+
+pub fn record_expired_sessions_deletion(outcome: &'static str) {
+    counter!("expired_sessions_deletions_total", "outcome" => outcome).increment(1);
+}
+
+pub fn record_purged_accounts(count: usize) {
+    counter!("deleted_accounts_purged_total").increment(count as u64);
+}
+
+pub fn record_db_pool_stats(db: &Database) {
+    gauge!("db_pool_connections").set(db.size() as f64);
+    gauge!("db_pool_idle_connections").set(db.num_idle() as f64);
+    gauge!("db_pool_max_connections").set(db.options().get_max_connections() as f64);
+}
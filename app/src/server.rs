@@ -1,73 +1,310 @@
 use crate::{
     api::{
-        layer::create_auth_layer,
-        middleware::{set_default_response_headers, set_request_render_options},
+        admin::create_admin_router,
+        error::{handle_panic, render_error_pages},
+        layer::{create_auth_layer, create_trace_layer},
+        metrics::create_metrics_router,
+        middleware::{
+            append_out_of_band_swaps, deliver_flash_messages, discard_invalid_request_id,
+            enforce_session_lifetime, record_http_metrics, record_user_id, resign_session_cookie,
+            save_resigned_session, set_default_response_headers, set_request_render_options,
+        },
         router::create_api_router,
     },
-    config::Config,
-    db::connection::{setup_db_pool, setup_session_store, Database, SessionStore},
-    libs::signal::shutdown_signal,
-    state::AppState,
-    tracing::setup_tracing,
+    config::{AdminConfig, Config},
+    controllers::account::{purge_deleted_accounts, DATA_EXPORT_TIMEOUT_MINUTES},
+    db::{
+        connection::{setup_db_pool, setup_session_store, Database, SessionStore},
+        data_export::{delete_expired_data_exports, fail_stale_data_exports},
+        session::delete_stale_user_sessions,
+    },
+    libs::{
+        auth::Backend,
+        health::Readiness,
+        secret_key::SecretKeys,
+        session_lifetime::{SessionPolicies, SessionPolicy},
+        signal::shutdown_signal,
+    },
+    metrics::{record_expired_sessions_deletion, record_purged_accounts, setup_metrics},
+    state::{AdminState, AppState, MetricsState, SessionCookieState},
+    tracing::{setup_tracing, LogFilterHandle},
 };
 use axum::{
-    middleware::{map_request, map_response},
+    middleware::{from_fn, from_fn_with_state, map_request, map_response},
     Router,
 };
 use std::net::SocketAddr;
 use time::Duration;
-use tokio::{task::spawn, time::Duration as TaskDuration};
+use tokio::{
+    task::spawn,
+    time::{interval, Duration as TaskDuration},
+};
 use tower::ServiceBuilder;
+use tower_http::{
+    catch_panic::CatchPanicLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+};
 use tower_sessions::ExpiredDeletion;
-use tracing::info;
-
-const PORT: u16 = 3000;
+use tracing::{error, info, warn};
 
-pub async fn run_server() {
-    let config = Config::from_env();
-
-    setup_tracing(&config.logging.level);
+pub async fn run_server(config: Config) {
+    let logging = setup_tracing(&config.logging, &config.telemetry);
+    // Metrics are recorded from the start, wherever they are served
+    setup_metrics();
+    for warning in &config.warnings {
+        warn!("{}", warning);
+    }
 
     let db = setup_db_pool(&config.db.url, config.db.pool_max_connections).await;
     let session_store = setup_session_store(db.clone()).await;
+    if let Err(e) = fail_stale_data_exports(DATA_EXPORT_TIMEOUT_MINUTES, &db).await {
+        error!("Failed to fail abandoned data exports: {:?}", e);
+    }
 
-    let router = create_router(&config, db, session_store.clone());
+    let readiness = Readiness::default();
+    let router = create_router(
+        &config,
+        db.clone(),
+        session_store.clone(),
+        readiness.clone(),
+    );
 
-    let socket_address = SocketAddr::from(([0, 0, 0, 0], PORT));
+    let socket_address = config.server.address();
     let listener = tokio::net::TcpListener::bind(&socket_address)
         .await
         .expect(&format!(
             "Failed to create listener bound to the {}",
             &socket_address
         ));
-    let deletion_task = spawn(
-        session_store.continuously_delete_expired(TaskDuration::from_secs(
-            config.auth.delete_expired_sessions_interval_seconds,
-        )),
-    );
+    let deletion_task = spawn(continuously_delete_expired_sessions(
+        session_store.clone(),
+        db.clone(),
+        TaskDuration::from_secs(config.auth.delete_expired_sessions_interval_seconds),
+    ));
+    let purge_task = spawn(continuously_purge_deleted_data(
+        db.clone(),
+        TaskDuration::from_secs(config.account.purge_deleted_interval_seconds),
+    ));
+
+    if let Some(admin_address) = config.admin.address {
+        let admin_router =
+            create_admin_server_router(&config.admin, db, logging.log_filter.clone());
+        spawn(run_admin_server(admin_address, admin_router));
+    }
 
     info!("Running server on {}", socket_address);
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal(
+        vec![deletion_task.abort_handle(), purge_task.abort_handle()],
+        readiness,
+        TaskDuration::from_secs(config.server.shutdown_readiness_delay_seconds),
+    ))
+    .await
+    .expect("Failed to run the server");
+
+    logging.shutdown();
+}
+
+async fn run_admin_server(socket_address: SocketAddr, router: Router) {
+    let listener = tokio::net::TcpListener::bind(&socket_address)
+        .await
+        .expect(&format!(
+            "Failed to create admin listener bound to the {}",
+            &socket_address
+        ));
+    info!("Running admin server on {}", socket_address);
     axum::serve(listener, router)
-        .with_graceful_shutdown(shutdown_signal(deletion_task.abort_handle()))
         .await
-        .expect("Failed to run the server");
+        .expect("Failed to run the admin server");
+}
+
+async fn continuously_delete_expired_sessions(
+    session_store: SessionStore,
+    db: Database,
+    period: TaskDuration,
+) {
+    let mut interval = interval(period);
+    loop {
+        interval.tick().await;
+        match session_store.delete_expired().await {
+            Ok(()) => record_expired_sessions_deletion("success"),
+            Err(e) => {
+                record_expired_sessions_deletion("failure");
+                error!("Failed to delete expired sessions: {:?}", e);
+            }
+        }
+        if let Err(e) = delete_stale_user_sessions(&db).await {
+            error!("Failed to delete stale user sessions: {:?}", e);
+        }
+    }
+}
+
+async fn continuously_purge_deleted_data(db: Database, period: TaskDuration) {
+    let mut interval = interval(period);
+    loop {
+        interval.tick().await;
+        match purge_deleted_accounts(&db).await {
+            Ok(count) => record_purged_accounts(count),
+            Err(e) => error!("Failed to purge deleted accounts: {:?}", e),
+        }
+        if let Err(e) = delete_expired_data_exports(&db).await {
+            error!("Failed to delete expired data exports: {:?}", e);
+        }
+        if let Err(e) = fail_stale_data_exports(DATA_EXPORT_TIMEOUT_MINUTES, &db).await {
+            error!("Failed to fail abandoned data exports: {:?}", e);
+        }
+    }
+}
+
+// Only served on the admin address, as the metrics and the admin endpoints
+// must not be reachable from the outside
+pub fn create_admin_server_router(
+    config: &AdminConfig,
+    db: Database,
+    log_filter: LogFilterHandle,
+) -> Router {
+    create_admin_router(AdminState {
+        log_filter,
+        db: db.clone(),
+        token: config.token.clone(),
+    })
+    .merge(create_metrics_router(MetricsState {
+        metrics: setup_metrics(),
+        db,
+    }))
 }
 
-pub fn create_router(config: &Config, db: Database, session_store: SessionStore) -> Router {
+pub fn create_router(
+    config: &Config,
+    db: Database,
+    session_store: SessionStore,
+    readiness: Readiness,
+) -> Router {
+    create_router_with_routes(config, db, session_store, readiness, Router::new())
+}
+
+// The extra routes get the same middleware as the app routes, e.g. to test
+// how the app handles failing handlers.
+fn create_router_with_routes(
+    config: &Config,
+    db: Database,
+    session_store: SessionStore,
+    readiness: Readiness,
+    routes: Router,
+) -> Router {
+    let secret_keys = SecretKeys::new(&config.auth.secret_keys);
     let auth_layer = create_auth_layer(
         session_store,
-        db.clone(),
-        &config.auth.secret_key,
-        Duration::minutes(config.auth.session_expiration_minutes),
+        Backend::new(db.clone(), config.password.hashing.clone()),
+        &config.auth.cookie,
+        &secret_keys,
+        Duration::minutes(config.auth.session.idle_timeout_minutes),
     );
-    let state = AppState::new(db);
+    let session_policies = SessionPolicies {
+        default: SessionPolicy {
+            idle_timeout: Duration::minutes(config.auth.session.idle_timeout_minutes),
+            absolute_lifetime: Duration::minutes(config.auth.session.absolute_lifetime_minutes),
+        },
+        remember_me: SessionPolicy {
+            idle_timeout: Duration::minutes(config.auth.remember_me.idle_timeout_minutes),
+            absolute_lifetime: Duration::minutes(config.auth.remember_me.absolute_lifetime_minutes),
+        },
+    };
+    let session_cookie = SessionCookieState {
+        name: config.auth.cookie.name.clone(),
+        protection: config.auth.cookie.protection,
+        secret_keys: secret_keys.clone(),
+    };
+    // Served without the app middleware, like on the admin server
+    let metrics_router = match config.admin.address {
+        Some(_) => Router::new(),
+        None => create_metrics_router(MetricsState {
+            metrics: setup_metrics(),
+            db: db.clone(),
+        }),
+    };
+    let state = AppState::new(db, readiness, config, secret_keys);
     Router::new()
         .merge(create_api_router())
         .with_state(state)
+        .merge(routes)
         .layer(
             ServiceBuilder::new()
+                .layer(map_request(discard_invalid_request_id))
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(create_trace_layer())
+                .layer(from_fn(record_http_metrics))
+                .layer(PropagateRequestIdLayer::x_request_id())
+                .layer(from_fn_with_state(session_cookie, resign_session_cookie))
                 .layer(auth_layer)
+                .layer(from_fn(save_resigned_session))
+                .layer(from_fn_with_state(
+                    session_policies,
+                    enforce_session_lifetime,
+                ))
+                .layer(from_fn(record_user_id))
+                .layer(map_response(append_out_of_band_swaps))
+                .layer(from_fn(deliver_flash_messages))
                 .layer(map_request(set_request_render_options))
+                .layer(from_fn(render_error_pages))
+                .layer(CatchPanicLayer::custom(handle_panic))
                 .layer(map_response(set_default_response_headers)),
         )
+        .merge(metrics_router)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::create_router_with_routes;
+    use crate::{
+        config::Config,
+        db::connection::{setup_session_store, Database},
+        libs::health::Readiness,
+    };
+    use axum::{
+        body::{to_bytes, Body},
+        extract::Request,
+        http::{header::CONTENT_TYPE, StatusCode},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    #[sqlx::test]
+    async fn render_500_page_when_handler_panics(db: Database) {
+        let session_store = setup_session_store(db.clone()).await;
+        let router = create_router_with_routes(
+            &Config::from_env(),
+            db,
+            session_store,
+            Readiness::default(),
+            Router::new().route("/panic", get(|| async { panic!("Handler failure") })),
+        );
+
+        let response = router
+            .oneshot(
+                Request::builder()
+                    .uri("/panic")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(response
+            .headers()
+            .get(CONTENT_TYPE)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(body.contains("Something went wrong"));
+        assert!(!body.contains("Handler failure"));
+    }
 }
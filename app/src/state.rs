@@ -1,12 +1,92 @@
-use crate::db::connection::Database;
+use crate::{
+    api::constant::{
+        EMAIL_AVAILABILITY_CHECK_LIMIT, EMAIL_AVAILABILITY_CHECK_WINDOW, REAUTHENTICATION_LIMIT,
+        REAUTHENTICATION_WINDOW,
+    },
+    config::Config,
+    db::connection::Database,
+    libs::{
+        breached_password::{BreachedPasswordRanges, LocalBreachedPasswordRanges},
+        health::Readiness,
+        password::PasswordHashing,
+        password_policy::PasswordPolicy,
+        password_rotation::PasswordRotationPolicy,
+        rate_limit::RateLimiter,
+        secret_key::{CookieProtection, SecretKeys},
+    },
+    tracing::LogFilterHandle,
+};
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::{path::PathBuf, sync::Arc};
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: Database,
+    pub email_availability_limiter: RateLimiter,
+    pub reauthentication_limiter: RateLimiter,
+    pub readiness: Readiness,
+    pub password_hashing: PasswordHashing,
+    pub password_policy: PasswordPolicy,
+    pub breached_password_ranges: Option<Arc<dyn BreachedPasswordRanges>>,
+    pub breached_password_check_fail_open: bool,
+    pub password_rotation: PasswordRotationPolicy,
+    pub account_deletion_grace_period_days: u16,
+    pub data_export_expiry_hours: u16,
+    pub secret_keys: SecretKeys,
 }
 
 impl AppState {
-    pub fn new(db: Database) -> Self {
-        Self { db }
+    pub fn new(
+        db: Database,
+        readiness: Readiness,
+        config: &Config,
+        secret_keys: SecretKeys,
+    ) -> Self {
+        let password = &config.password;
+        let breached_password_ranges = password.breached_ranges_directory.as_ref().map(
+            |directory| -> Arc<dyn BreachedPasswordRanges> {
+                Arc::new(LocalBreachedPasswordRanges::new(PathBuf::from(directory)))
+            },
+        );
+        Self {
+            db,
+            email_availability_limiter: RateLimiter::new(
+                EMAIL_AVAILABILITY_CHECK_LIMIT,
+                EMAIL_AVAILABILITY_CHECK_WINDOW,
+            ),
+            reauthentication_limiter: RateLimiter::new(
+                REAUTHENTICATION_LIMIT,
+                REAUTHENTICATION_WINDOW,
+            ),
+            readiness,
+            password_hashing: password.hashing.clone(),
+            password_policy: password.policy,
+            breached_password_ranges,
+            breached_password_check_fail_open: password.breached_check_fail_open,
+            password_rotation: password.rotation,
+            account_deletion_grace_period_days: config.account.deletion_grace_period_days,
+            data_export_expiry_hours: config.account.data_export_expiry_hours,
+            secret_keys,
+        }
     }
 }
+
+#[derive(Clone)]
+pub struct SessionCookieState {
+    pub name: String,
+    pub protection: CookieProtection,
+    pub secret_keys: SecretKeys,
+}
+
+#[derive(Clone)]
+pub struct AdminState {
+    pub log_filter: LogFilterHandle,
+    pub db: Database,
+    pub token: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct MetricsState {
+    pub metrics: PrometheusHandle,
+    pub db: Database,
+}
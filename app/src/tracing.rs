@@ -1,12 +1,222 @@
-use std::str::FromStr;
-use tracing::Level;
-use tracing_subscriber::fmt;
-
-pub fn setup_tracing(log_level: &str) {
-    fmt()
-        .with_max_level(
-            Level::from_str(&log_level).expect(&format!("Invalid log level {}", log_level)),
-        )
-        .compact()
+use crate::{
+    config::{LogFileRotation, LogFormat, LoggingConfig, TelemetryConfig},
+    libs::redact::RedactingFormat,
+};
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    runtime::Tokio,
+    trace::{Config as TraceConfig, TracerProvider},
+    Resource,
+};
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result as FormatResult},
+    io::stdout,
+};
+use tracing::{error, Subscriber};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    filter::ParseError,
+    fmt::{self, format::JsonFields, layer, writer::BoxMakeWriter},
+    layer::{Layer, SubscriberExt},
+    registry::LookupSpan,
+    reload::{Error as ReloadError, Handle, Layer as ReloadLayer},
+    util::SubscriberInitExt,
+    EnvFilter, Registry,
+};
+
+pub struct Tracing {
+    pub log_filter: LogFilterHandle,
+    // Buffered logs are flushed to the file when the guard is dropped, so it
+    // must live as long as the server.
+    _file_guard: Option<WorkerGuard>,
+    tracer_provider: Option<TracerProvider>,
+}
+
+impl Tracing {
+    // Exports the spans which are still buffered
+    pub fn shutdown(&self) {
+        if let Some(tracer_provider) = &self.tracer_provider {
+            if let Err(e) = tracer_provider.shutdown() {
+                error!("Failed to shut down the tracer provider: {:?}", e);
+            }
+        }
+    }
+}
+
+pub fn setup_tracing(config: &LoggingConfig, telemetry_config: &TelemetryConfig) -> Tracing {
+    let filter = EnvFilter::try_new(&config.filter)
+        .unwrap_or_else(|e| panic!("Invalid log filter {}: {}", config.filter, e));
+    let (filter, filter_handle) = ReloadLayer::new(filter);
+
+    let (writer, file_guard) = match &config.file {
+        None => (BoxMakeWriter::new(stdout), None),
+        Some(file) => {
+            let appender = RollingFileAppender::new(
+                get_rotation(&file.rotation),
+                &file.directory,
+                &file.prefix,
+            );
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+    };
+
+    let tracer_provider = telemetry_config
+        .otlp_endpoint
+        .as_ref()
+        .map(|endpoint| create_otlp_tracer_provider(endpoint, &telemetry_config.service_name));
+    let telemetry_layer = tracer_provider.as_ref().map(|tracer_provider| {
+        tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("app"))
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(create_log_layer(config.format, writer))
+        .with(telemetry_layer)
         .init();
+
+    Tracing {
+        log_filter: LogFilterHandle::new(filter_handle),
+        _file_guard: file_guard,
+        tracer_provider,
+    }
+}
+
+// Every log line goes through the redaction, whatever the format or writer
+pub fn create_log_layer<S>(
+    format: LogFormat,
+    writer: BoxMakeWriter,
+) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    match format {
+        LogFormat::Json => layer()
+            .fmt_fields(JsonFields::new())
+            .event_format(RedactingFormat::new(
+                fmt::format()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(false),
+            ))
+            .with_writer(writer)
+            .boxed(),
+        LogFormat::Compact => layer()
+            .event_format(RedactingFormat::new(fmt::format().compact()))
+            .with_writer(writer)
+            .boxed(),
+    }
+}
+
+fn create_otlp_tracer_provider(endpoint: &str, service_name: &str) -> TracerProvider {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            TraceConfig::default().with_resource(Resource::new([KeyValue::new(
+                "service.name",
+                service_name.to_owned(),
+            )])),
+        )
+        .install_batch(Tokio)
+        .expect("Failed to create the OTLP trace exporter")
+}
+
+fn get_rotation(rotation: &LogFileRotation) -> Rotation {
+    match rotation {
+        LogFileRotation::Hourly => Rotation::HOURLY,
+        LogFileRotation::Daily => Rotation::DAILY,
+        LogFileRotation::Never => Rotation::NEVER,
+    }
+}
+
+#[derive(Debug)]
+pub enum LogFilterError {
+    ParseError(ParseError),
+    ReloadError(ReloadError),
+}
+
+impl Error for LogFilterError {}
+
+impl Display for LogFilterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FormatResult {
+        match self {
+            LogFilterError::ParseError(e) => {
+                write!(f, "Parse error: {}", e)
+            }
+            LogFilterError::ReloadError(e) => {
+                write!(f, "Reload error: {}", e)
+            }
+        }
+    }
+}
+
+impl From<ParseError> for LogFilterError {
+    fn from(value: ParseError) -> Self {
+        LogFilterError::ParseError(value)
+    }
+}
+
+impl From<ReloadError> for LogFilterError {
+    fn from(value: ReloadError) -> Self {
+        LogFilterError::ReloadError(value)
+    }
+}
+
+#[derive(Clone)]
+pub struct LogFilterHandle {
+    handle: Handle<EnvFilter, Registry>,
+}
+
+impl LogFilterHandle {
+    pub fn new(handle: Handle<EnvFilter, Registry>) -> Self {
+        Self { handle }
+    }
+
+    pub fn get(&self) -> Result<String, LogFilterError> {
+        Ok(self.handle.with_current(|filter| filter.to_string())?)
+    }
+
+    pub fn set(&self, directives: &str) -> Result<(), LogFilterError> {
+        let filter = EnvFilter::try_new(directives)?;
+        Ok(self.handle.reload(filter)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LogFilterError, LogFilterHandle};
+    use tracing_subscriber::{reload::Layer as ReloadLayer, EnvFilter};
+
+    #[test]
+    fn change_log_filter() {
+        let (_layer, handle) = ReloadLayer::new(EnvFilter::new("info"));
+        let log_filter = LogFilterHandle::new(handle);
+
+        log_filter.set("warn,app::api=debug").unwrap();
+
+        let directives = log_filter.get().unwrap();
+        assert!(directives.contains("app::api=debug"));
+        assert!(directives.contains("warn"));
+    }
+
+    #[test]
+    fn reject_invalid_log_filter() {
+        let (_layer, handle) = ReloadLayer::new(EnvFilter::new("info"));
+        let log_filter = LogFilterHandle::new(handle);
+
+        let result = log_filter.set("app=invalid_level");
+
+        assert!(matches!(result, Err(LogFilterError::ParseError(_))));
+        assert_eq!(log_filter.get().unwrap(), "info");
+    }
 }
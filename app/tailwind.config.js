@@ -1,6 +1,8 @@
 /** @type {import('tailwindcss').Config} */
 module.exports = {
   content: ["./templates/**/*.html"],
+  // Flash message alerts are built from the message level
+  safelist: ["alert-info", "alert-success", "alert-error"],
   theme: {
     extend: {},
   },
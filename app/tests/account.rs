@@ -0,0 +1,602 @@
+use app::db::{
+    connection::Database,
+    data_export::{create_data_export, fail_stale_data_exports},
+    session::delete_user_sessions,
+    user::{cancel_user_deletion, delete_users_past_scheduled_deletion},
+};
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE, COOKIE, LOCATION, SET_COOKIE},
+        HeaderValue, Method, StatusCode,
+    },
+    response::Response,
+    Router,
+};
+use mime::APPLICATION_WWW_FORM_URLENCODED;
+use std::time::Duration;
+use tokio::time::sleep;
+use tower::ServiceExt;
+use urlencoding::encode;
+
+pub mod common;
+use common::{
+    create_test_router, create_test_router_with_config, get_authenticated_user_cookie, read_body,
+    sign_in, TEST_PASSWORD,
+};
+
+const NEW_PASSWORD: &str = "correct horse battery staple";
+
+async fn post_delete_account(router: Router, cookie: HeaderValue) -> Response {
+    router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/account/delete")
+                .header("HX-Request", "true")
+                .header(COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+async fn get_with_cookie(router: Router, uri: &str, cookie: Option<HeaderValue>) -> Response {
+    let mut request = Request::builder().uri(uri);
+    if let Some(cookie) = cookie {
+        request = request.header(COOKIE, cookie);
+    }
+    router
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+// Requests an export and polls its status until the download links show up
+async fn get_data_export_download_url(router: Router, cookie: HeaderValue) -> String {
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/account/export")
+                .header("HX-Request", "true")
+                .header(COOKIE, cookie.clone())
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert_eq!(
+        response.headers().get("HX-Trigger").unwrap(),
+        r#"{"data-export-requested":null}"#
+    );
+
+    for _ in 0..50 {
+        let response = get_with_cookie(
+            router.clone(),
+            "/account/export/status",
+            Some(cookie.clone()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = read_body(response).await;
+        if let Some(start) = body.find("href=\"/account/export/download") {
+            let url = &body[start + 6..];
+            let end = url.find("&amp;format").unwrap();
+            return url[..end].replace("&amp;", "&");
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    panic!("Data export was not ready in time");
+}
+
+async fn post_change_password(router: Router, cookie: HeaderValue, password: &str) -> Response {
+    router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/account/password")
+                .header("HX-Request", "true")
+                .header(COOKIE, cookie)
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(format!(
+                    "password={}&confirm_password={}",
+                    encode(password),
+                    encode(password)
+                )))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn change_password(db: Database) {
+    let router = create_test_router(db.clone()).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+
+    let response = post_change_password(router.clone(), auth_cookie, NEW_PASSWORD).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("HX-Location").is_some());
+    let history_size: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM password_history")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(history_size, 2);
+
+    let protected_response = router
+        .oneshot(
+            Request::builder()
+                .uri("/protected")
+                .header(COOKIE, response.headers().get(SET_COOKIE).unwrap())
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(protected_response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn sign_out_other_sessions_after_password_change(db: Database) {
+    let router = create_test_router(db).await;
+    let other_session_cookie = get_authenticated_user_cookie(router.clone()).await;
+    let response = sign_in(router.clone()).await;
+    let auth_cookie = response.headers().get(SET_COOKIE).unwrap().to_owned();
+
+    let response = post_change_password(router.clone(), auth_cookie, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = get_with_cookie(router, "/protected", Some(other_session_cookie)).await;
+
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+}
+
+#[sqlx::test]
+async fn change_password_rejects_reused_password(db: Database) {
+    let router = create_test_router_with_config(db, |config| {
+        config.password.rotation.history_size = 2;
+    })
+    .await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+
+    let response = post_change_password(router.clone(), auth_cookie, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let auth_cookie = response.headers().get(SET_COOKIE).unwrap().to_owned();
+
+    for password in [TEST_PASSWORD, NEW_PASSWORD] {
+        let response = post_change_password(router.clone(), auth_cookie.clone(), password).await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(read_body(response)
+            .await
+            .contains("You have used this password recently"));
+    }
+}
+
+#[sqlx::test]
+async fn change_password_rejects_passwords_within_history_size(db: Database) {
+    let router = create_test_router_with_config(db, |config| {
+        config.password.rotation.history_size = 2;
+    })
+    .await;
+    let mut auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+    for password in [NEW_PASSWORD, "purple monkey dishwasher 42"] {
+        let response = post_change_password(router.clone(), auth_cookie, password).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        auth_cookie = response.headers().get(SET_COOKIE).unwrap().to_owned();
+    }
+
+    // The initial password is now the 2nd previous one
+    let response = post_change_password(router.clone(), auth_cookie.clone(), TEST_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response =
+        post_change_password(router.clone(), auth_cookie, "velvet kettle orbit 1987").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let auth_cookie = response.headers().get(SET_COOKIE).unwrap().to_owned();
+
+    // And then the 3rd previous one, which can be reused
+    let response = post_change_password(router, auth_cookie, TEST_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn require_password_change_after_expiry(db: Database) {
+    let router = create_test_router_with_config(db.clone(), |config| {
+        config.password.rotation.max_age_days = 90;
+    })
+    .await;
+    get_authenticated_user_cookie(router.clone()).await;
+    sqlx::query("UPDATE users SET password_changed_at = CURRENT_TIMESTAMP - INTERVAL '91 days'")
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let signin_response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(format!(
+                    "email={}&password={}",
+                    encode("test@example.com"),
+                    encode(TEST_PASSWORD)
+                )))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(signin_response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(
+        signin_response.headers().get(LOCATION).unwrap(),
+        "/account/password?next=%2Fprotected"
+    );
+    let auth_cookie = signin_response
+        .headers()
+        .get(SET_COOKIE)
+        .unwrap()
+        .to_owned();
+
+    let protected_response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/protected")
+                .header(COOKIE, auth_cookie.clone())
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(protected_response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(
+        protected_response.headers().get(LOCATION).unwrap(),
+        "/account/password?next=%2Fprotected"
+    );
+
+    let response = post_change_password(router.clone(), auth_cookie, NEW_PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let protected_response = router
+        .oneshot(
+            Request::builder()
+                .uri("/protected")
+                .header(COOKIE, response.headers().get(SET_COOKIE).unwrap())
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(protected_response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn require_password_change_on_account_pages(db: Database) {
+    let router = create_test_router(db.clone()).await;
+    get_authenticated_user_cookie(router.clone()).await;
+    sqlx::query("UPDATE users SET password_change_required = TRUE")
+        .execute(&db)
+        .await
+        .unwrap();
+    let signin_response = sign_in(router.clone()).await;
+    let auth_cookie = signin_response
+        .headers()
+        .get(SET_COOKIE)
+        .unwrap()
+        .to_owned();
+
+    for (uri, location) in [
+        (
+            "/account/delete",
+            "/account/password?next=%2Faccount%2Fdelete",
+        ),
+        (
+            "/account/export",
+            "/account/password?next=%2Faccount%2Fexport",
+        ),
+        (
+            "/account/export/status",
+            "/account/password?next=%2Faccount%2Fexport%2Fstatus",
+        ),
+    ] {
+        let response = get_with_cookie(router.clone(), uri, Some(auth_cookie.clone())).await;
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers().get(LOCATION).unwrap(), location);
+    }
+}
+
+#[sqlx::test]
+async fn exempt_user_from_password_expiry(db: Database) {
+    let router = create_test_router_with_config(db.clone(), |config| {
+        config.password.rotation.max_age_days = 90;
+    })
+    .await;
+    get_authenticated_user_cookie(router.clone()).await;
+    sqlx::query(
+        "UPDATE users SET password_changed_at = CURRENT_TIMESTAMP - INTERVAL '91 days', \
+            password_expiry_exempt = TRUE",
+    )
+    .execute(&db)
+    .await
+    .unwrap();
+
+    let signin_response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(format!(
+                    "email={}&password={}",
+                    encode("test@example.com"),
+                    encode(TEST_PASSWORD)
+                )))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        signin_response.headers().get(LOCATION).unwrap(),
+        "/protected"
+    );
+}
+
+#[sqlx::test]
+async fn delete_account(db: Database) {
+    let router = create_test_router(db.clone()).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+
+    let response = post_delete_account(router.clone(), auth_cookie.clone()).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let scheduled: bool = sqlx::query_scalar("SELECT scheduled_deletion_at IS NOT NULL FROM users")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert!(scheduled);
+    let user_id: i32 = sqlx::query_scalar("SELECT id FROM users")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(delete_user_sessions(&[user_id], &db).await.unwrap(), 0);
+
+    let protected_response = router
+        .oneshot(
+            Request::builder()
+                .uri("/protected")
+                .header(COOKIE, auth_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(protected_response.status(), StatusCode::TEMPORARY_REDIRECT);
+}
+
+#[sqlx::test]
+async fn cancel_account_deletion_by_signing_in(db: Database) {
+    let router = create_test_router(db.clone()).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+    post_delete_account(router.clone(), auth_cookie).await;
+
+    let response = sign_in(router).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let scheduled: bool = sqlx::query_scalar("SELECT scheduled_deletion_at IS NOT NULL FROM users")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert!(!scheduled);
+}
+
+#[sqlx::test]
+async fn sign_out_other_sessions_on_account_deletion(db: Database) {
+    let router = create_test_router(db).await;
+    let other_session_cookie = get_authenticated_user_cookie(router.clone()).await;
+    let signin_response = sign_in(router.clone()).await;
+    let auth_cookie = signin_response
+        .headers()
+        .get(SET_COOKIE)
+        .unwrap()
+        .to_owned();
+
+    let response = post_delete_account(router.clone(), auth_cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = get_with_cookie(router, "/protected", Some(other_session_cookie)).await;
+
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+}
+
+#[sqlx::test]
+async fn dont_cancel_account_deletion_past_grace_period(db: Database) {
+    let router = create_test_router(db.clone()).await;
+    get_authenticated_user_cookie(router).await;
+    let user_id: i32 = sqlx::query_scalar(
+        "UPDATE users SET scheduled_deletion_at = CURRENT_TIMESTAMP - INTERVAL '1 day' \
+            RETURNING id",
+    )
+    .fetch_one(&db)
+    .await
+    .unwrap();
+
+    assert_eq!(cancel_user_deletion(user_id, &db).await.unwrap(), None);
+    delete_users_past_scheduled_deletion(&db).await.unwrap();
+    assert_eq!(cancel_user_deletion(user_id, &db).await.unwrap(), None);
+}
+
+#[sqlx::test]
+async fn purge_account_after_grace_period(db: Database) {
+    let router = create_test_router(db.clone()).await;
+    get_authenticated_user_cookie(router.clone()).await;
+    sqlx::query("UPDATE users SET scheduled_deletion_at = CURRENT_TIMESTAMP - INTERVAL '1 day'")
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let signin_response = sign_in(router).await;
+    assert_eq!(signin_response.status(), StatusCode::UNAUTHORIZED);
+
+    let user_ids = delete_users_past_scheduled_deletion(&db).await.unwrap();
+    let deleted_sessions = delete_user_sessions(&user_ids, &db).await.unwrap();
+
+    assert_eq!(user_ids.len(), 1);
+    assert_eq!(deleted_sessions, 1);
+    let history_size: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM password_history")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(history_size, 0);
+}
+
+#[sqlx::test]
+async fn record_sign_in_history(db: Database) {
+    let router = create_test_router(db.clone()).await;
+    get_authenticated_user_cookie(router.clone()).await;
+
+    let response = sign_in(router).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let sign_in_events: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sign_in_events")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(sign_in_events, 1);
+}
+
+#[sqlx::test]
+async fn export_personal_data(db: Database) {
+    let router = create_test_router(db).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+    sign_in(router.clone()).await;
+
+    let url = get_data_export_download_url(router.clone(), auth_cookie).await;
+
+    let json_response =
+        get_with_cookie(router.clone(), &format!("{}&format=json", url), None).await;
+    assert_eq!(json_response.status(), StatusCode::OK);
+    assert_eq!(
+        json_response.headers().get(CONTENT_DISPOSITION).unwrap(),
+        "attachment; filename=\"data-export.json\""
+    );
+    let data: serde_json::Value = serde_json::from_str(&read_body(json_response).await).unwrap();
+    assert_eq!(data["profile"]["email"], "test@example.com");
+    assert_eq!(data["sign_in_history"].as_array().unwrap().len(), 1);
+    assert_eq!(data["sessions"].as_array().unwrap().len(), 2);
+
+    let zip_response = get_with_cookie(router, &format!("{}&format=zip", url), None).await;
+    assert_eq!(zip_response.status(), StatusCode::OK);
+    assert_eq!(
+        zip_response.headers().get(CONTENT_TYPE).unwrap(),
+        "application/zip"
+    );
+}
+
+#[sqlx::test]
+async fn replace_previous_data_export(db: Database) {
+    let router = create_test_router(db.clone()).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+    let previous_url = get_data_export_download_url(router.clone(), auth_cookie.clone()).await;
+
+    let url = get_data_export_download_url(router.clone(), auth_cookie).await;
+
+    assert_ne!(url, previous_url);
+    let response = get_with_cookie(router.clone(), &previous_url, None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = get_with_cookie(router, &url, None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let exports: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM data_exports")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(exports, 1);
+}
+
+#[sqlx::test]
+async fn deliver_flash_messages_with_fragment(db: Database) {
+    let router = create_test_router(db).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+    let response = post_change_password(router.clone(), auth_cookie, NEW_PASSWORD).await;
+    let auth_cookie = response.headers().get(SET_COOKIE).unwrap().to_owned();
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/account/export/status")
+                .header("HX-Request", "true")
+                .header(COOKIE, auth_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = read_body(response).await;
+    assert!(body.contains("data-export-status"));
+    assert!(body.contains("hx-swap-oob"));
+    assert!(body.contains("Your password has been changed"));
+}
+
+#[sqlx::test]
+async fn reject_tampered_data_export_link(db: Database) {
+    let router = create_test_router(db).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+    let url = get_data_export_download_url(router.clone(), auth_cookie).await;
+
+    let (url, signature) = url.split_once("&signature=").unwrap();
+    let tampered_url = format!("{}&signature={}x&format=json", url, signature);
+    let response = get_with_cookie(router.clone(), &tampered_url, None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let extended_url = format!(
+        "{}&signature={}&format=json",
+        url.replace("expires=", "expires=1"),
+        signature
+    );
+    let response = get_with_cookie(router, &extended_url, None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[sqlx::test]
+async fn fail_abandoned_data_exports(db: Database) {
+    get_authenticated_user_cookie(create_test_router(db.clone()).await).await;
+    let user_id: i32 = sqlx::query_scalar("SELECT id FROM users")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    let export = create_data_export(user_id, 24, 15, &db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(fail_stale_data_exports(15, &db).await.unwrap(), 0);
+    sqlx::query("UPDATE data_exports SET created_at = CURRENT_TIMESTAMP - INTERVAL '1 hour'")
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let failed = fail_stale_data_exports(15, &db).await.unwrap();
+
+    assert_eq!(failed, 1);
+    let status: String = sqlx::query_scalar("SELECT status FROM data_exports WHERE id = $1")
+        .bind(export.id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(status, "failed");
+}
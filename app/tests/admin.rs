@@ -0,0 +1,131 @@
+use app::db::connection::Database;
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header::AUTHORIZATION, Method, StatusCode},
+    response::Response,
+    Router,
+};
+use tower::ServiceExt;
+
+pub mod common;
+use common::{
+    create_test_admin_router, create_test_router, get_authenticated_user_cookie, TEST_ADMIN_TOKEN,
+};
+
+async fn put_flag_with_token(router: Router, uri: &str, flag: &str, token: &str) -> Response {
+    router
+        .oneshot(
+            Request::builder()
+                .method(Method::PUT)
+                .uri(uri)
+                .header(AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::from(flag.to_owned()))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+async fn put_flag(router: Router, uri: &str, flag: &str) -> Response {
+    put_flag_with_token(router, uri, flag, TEST_ADMIN_TOKEN).await
+}
+
+async fn create_user(db: &Database) -> i32 {
+    get_authenticated_user_cookie(create_test_router(db.clone()).await).await;
+    sqlx::query_scalar("SELECT id FROM users WHERE email = $1")
+        .bind("test@example.com")
+        .fetch_one(db)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test]
+async fn require_password_change(db: Database) {
+    let id = create_user(&db).await;
+    let router = create_test_admin_router(db.clone());
+
+    let response = put_flag(
+        router,
+        &format!("/users/{}/password-change-required", id),
+        "true",
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let required: bool =
+        sqlx::query_scalar("SELECT password_change_required FROM users WHERE id = $1")
+            .bind(id)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert!(required);
+}
+
+#[sqlx::test]
+async fn exempt_user_from_password_expiry(db: Database) {
+    let id = create_user(&db).await;
+    let router = create_test_admin_router(db.clone());
+
+    let response = put_flag(
+        router,
+        &format!("/users/{}/password-expiry-exempt", id),
+        "true",
+    )
+    .await;
+
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let exempt: bool = sqlx::query_scalar("SELECT password_expiry_exempt FROM users WHERE id = $1")
+        .bind(id)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert!(exempt);
+}
+
+#[sqlx::test]
+async fn set_password_flags_of_unknown_user(db: Database) {
+    let router = create_test_admin_router(db);
+
+    for uri in [
+        "/users/42/password-change-required",
+        "/users/42/password-expiry-exempt",
+    ] {
+        let response = put_flag(router.clone(), uri, "true").await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
+
+#[sqlx::test]
+async fn reject_invalid_password_flag(db: Database) {
+    let id = create_user(&db).await;
+    let router = create_test_admin_router(db);
+
+    for uri in [
+        format!("/users/{}/password-change-required", id),
+        format!("/users/{}/password-expiry-exempt", id),
+    ] {
+        let response = put_flag(router.clone(), &uri, "yes").await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
+
+#[sqlx::test]
+async fn reject_password_flags_without_admin_token(db: Database) {
+    let id = create_user(&db).await;
+    let router = create_test_admin_router(db.clone());
+    let uri = format!("/users/{}/password-change-required", id);
+
+    let response = put_flag_with_token(router, &uri, "true", "wrong-token").await;
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let required: bool =
+        sqlx::query_scalar("SELECT password_change_required FROM users WHERE id = $1")
+            .bind(id)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert!(!required);
+}
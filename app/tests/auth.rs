@@ -1,18 +1,36 @@
-use app::db::connection::Database;
+use app::{
+    config::Config,
+    db::connection::Database,
+    import::{import_users, ImportedUser},
+    libs::{
+        password::{PasswordHashing, Pepper},
+        secret_key::CookieProtection,
+    },
+};
+use argon2::{Algorithm, Params};
 use axum::{
     body::Body,
-    extract::Request,
+    extract::{connect_info::MockConnectInfo, Request},
     http::{
-        header::{CONTENT_TYPE, COOKIE, LOCATION, SET_COOKIE},
+        header::{CONTENT_TYPE, COOKIE, LOCATION, RETRY_AFTER, SET_COOKIE},
         Method, StatusCode,
     },
+    response::Response,
+    Router,
 };
 use mime::APPLICATION_WWW_FORM_URLENCODED;
+use sha1::{Digest, Sha1};
+use std::{fs::write, net::SocketAddr};
+use tempfile::tempdir;
+use tokio::spawn;
 use tower::ServiceExt;
 use urlencoding::encode;
 
 pub mod common;
-use common::{create_test_router, get_authenticated_user_cookie, is_html_response};
+use common::{
+    create_test_router, create_test_router_with_config, get_authenticated_user_cookie,
+    is_html_response, read_body, TEST_PASSWORD,
+};
 
 struct SignupPayload<'a> {
     email: &'a str,
@@ -24,8 +42,8 @@ impl<'a> Default for SignupPayload<'a> {
     fn default() -> Self {
         Self {
             email: "test@example.com",
-            password: "password123",
-            confirm_password: "password123",
+            password: TEST_PASSWORD,
+            confirm_password: TEST_PASSWORD,
         }
     }
 }
@@ -50,7 +68,7 @@ impl<'a> Default for SigninPayload<'a> {
     fn default() -> Self {
         Self {
             email: "test@example.com",
-            password: "password123",
+            password: TEST_PASSWORD,
         }
     }
 }
@@ -114,6 +132,7 @@ async fn sign_up(db: Database) {
             Request::builder()
                 .method(Method::POST)
                 .uri("/signup")
+                .header("HX-Request", "true")
                 .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
                 .body(Body::from(payload.to_form_data()))
                 .unwrap(),
@@ -122,6 +141,7 @@ async fn sign_up(db: Database) {
         .unwrap();
 
     assert_eq!(signup_response.status(), StatusCode::CREATED);
+    assert!(signup_response.headers().contains_key("HX-Location"));
 
     let protected_response = router
         .oneshot(
@@ -158,6 +178,7 @@ async fn sign_up_with_invalid_email_payload(db: Database) {
                 Request::builder()
                     .method(Method::POST)
                     .uri("/signup")
+                    .header("HX-Request", "true")
                     .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
                     .body(Body::from(payload.to_form_data()))
                     .unwrap(),
@@ -187,6 +208,41 @@ async fn sign_up_with_invalid_password_payload(db: Database) {
                 Request::builder()
                     .method(Method::POST)
                     .uri("/signup")
+                    .header("HX-Request", "true")
+                    .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                    .body(Body::from(payload.to_form_data()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
+
+#[sqlx::test]
+async fn sign_up_with_password_rejected_by_policy(db: Database) {
+    let router = create_test_router(db).await;
+    let cases = [
+        ("Password123", "This password is too common"),
+        ("johnsmith-2024", "Password must not contain your email"),
+        ("abcabcabc", "Password is too easy to guess"),
+    ];
+
+    for (password, message) in cases {
+        let payload = SignupPayload {
+            email: "johnsmith@example.com",
+            password,
+            confirm_password: password,
+        };
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/signup")
+                    .header("HX-Request", "true")
                     .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
                     .body(Body::from(payload.to_form_data()))
                     .unwrap(),
@@ -195,6 +251,7 @@ async fn sign_up_with_invalid_password_payload(db: Database) {
             .unwrap();
 
         assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(read_body(response).await.contains(message));
     }
 }
 
@@ -205,7 +262,7 @@ async fn sign_up_with_invalid_confirm_password_payload(db: Database) {
 
     for case in cases {
         let payload = SignupPayload {
-            password: "password123",
+            password: TEST_PASSWORD,
             confirm_password: case,
             ..Default::default()
         };
@@ -216,6 +273,7 @@ async fn sign_up_with_invalid_confirm_password_payload(db: Database) {
                 Request::builder()
                     .method(Method::POST)
                     .uri("/signup")
+                    .header("HX-Request", "true")
                     .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
                     .body(Body::from(payload.to_form_data()))
                     .unwrap(),
@@ -238,6 +296,7 @@ async fn sign_up_with_already_existing_email(db: Database) {
             Request::builder()
                 .method(Method::POST)
                 .uri("/signup")
+                .header("HX-Request", "true")
                 .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
                 .body(Body::from(payload.to_form_data()))
                 .unwrap(),
@@ -252,6 +311,7 @@ async fn sign_up_with_already_existing_email(db: Database) {
             Request::builder()
                 .method(Method::POST)
                 .uri("/signup")
+                .header("HX-Request", "true")
                 .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
                 .body(Body::from(payload.to_form_data()))
                 .unwrap(),
@@ -263,120 +323,131 @@ async fn sign_up_with_already_existing_email(db: Database) {
 }
 
 #[sqlx::test]
-async fn get_signin_page(db: Database) {
+async fn sign_up_without_htmx(db: Database) {
     let router = create_test_router(db).await;
+    let payload = SignupPayload::default();
 
-    let response = router
+    let signup_response = router
+        .clone()
         .oneshot(
             Request::builder()
-                .uri("/signin")
-                .body(Body::empty())
+                .method(Method::POST)
+                .uri("/signup")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(payload.to_form_data()))
                 .unwrap(),
         )
         .await
         .unwrap();
 
-    assert_eq!(response.status(), StatusCode::OK);
-    assert!(is_html_response(&response));
-}
-
-#[sqlx::test]
-async fn get_signin_page_redirect_on_authenticated(db: Database) {
-    let router = create_test_router(db).await;
-    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+    assert_eq!(signup_response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(
+        signup_response.headers().get(LOCATION).unwrap(),
+        "/protected"
+    );
 
-    let response = router
+    let protected_response = router
         .oneshot(
             Request::builder()
-                .uri("/signin")
-                .header(COOKIE, auth_cookie)
+                .uri("/protected")
+                .header(COOKIE, signup_response.headers().get(SET_COOKIE).unwrap())
                 .body(Body::empty())
                 .unwrap(),
         )
         .await
         .unwrap();
 
-    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
-    assert_eq!(response.headers().get(LOCATION).unwrap(), "/protected");
+    assert_eq!(protected_response.status(), StatusCode::OK);
 }
 
 #[sqlx::test]
-async fn sign_in(db: Database) {
+async fn sign_up_with_invalid_payload_renders_form_fragment(db: Database) {
     let router = create_test_router(db).await;
-    let signup_payload = SignupPayload {
-        email: "test@example.com",
-        password: "password123",
-        confirm_password: "password123",
+    let payload = SignupPayload {
+        email: "invalid-email",
+        ..Default::default()
     };
 
-    let signup_response = router
-        .clone()
+    let response = router
         .oneshot(
             Request::builder()
                 .method(Method::POST)
                 .uri("/signup")
+                .header("HX-Request", "true")
                 .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
-                .body(Body::from(signup_payload.to_form_data()))
+                .body(Body::from(payload.to_form_data()))
                 .unwrap(),
         )
         .await
         .unwrap();
 
-    assert_eq!(signup_response.status(), StatusCode::CREATED);
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(!read_body(response).await.contains("<!DOCTYPE html>"));
+}
 
-    let signin_payload = SigninPayload {
-        email: "test@example.com",
-        password: "password123",
+#[sqlx::test]
+async fn sign_up_with_invalid_payload_without_htmx_renders_full_page(db: Database) {
+    let router = create_test_router(db).await;
+    let payload = SignupPayload {
+        email: "invalid-email",
+        ..Default::default()
     };
 
-    let signin_response = router
-        .clone()
+    let response = router
         .oneshot(
             Request::builder()
                 .method(Method::POST)
-                .uri("/signin")
+                .uri("/signup")
                 .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
-                .body(Body::from(signin_payload.to_form_data()))
+                .body(Body::from(payload.to_form_data()))
                 .unwrap(),
         )
         .await
         .unwrap();
 
-    assert_eq!(signin_response.status(), StatusCode::OK);
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(is_html_response(&response));
+    assert!(read_body(response).await.contains("<!DOCTYPE html>"));
+}
 
-    let protected_response = router
+#[sqlx::test]
+async fn validate_signup_email(db: Database) {
+    let router = create_test_router(db).await;
+
+    let response = router
         .oneshot(
             Request::builder()
-                .uri("/protected")
-                .header(COOKIE, signin_response.headers().get(SET_COOKIE).unwrap())
-                .body(Body::empty())
+                .method(Method::POST)
+                .uri("/signup/email")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(format!("email={}", encode("test@example.com"))))
                 .unwrap(),
         )
         .await
         .unwrap();
 
-    assert_eq!(protected_response.status(), StatusCode::OK);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(is_html_response(&response));
 }
 
 #[sqlx::test]
-async fn sign_in_with_invalid_email_payload(db: Database) {
+async fn validate_signup_email_with_invalid_payload(db: Database) {
     let router = create_test_router(db).await;
-    let cases = ["", "invalid-email"];
+    let cases = [
+        "",
+        &format!("{}@email.com", "a".repeat(245)),
+        "invalid-email",
+    ];
 
     for case in cases {
-        let payload = SigninPayload {
-            email: case,
-            ..Default::default()
-        };
-
         let response = router
             .clone()
             .oneshot(
                 Request::builder()
                     .method(Method::POST)
-                    .uri("/signin")
+                    .uri("/signup/email")
                     .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
-                    .body(Body::from(payload.to_form_data()))
+                    .body(Body::from(format!("email={}", encode(case))))
                     .unwrap(),
             )
             .await
@@ -387,116 +458,234 @@ async fn sign_in_with_invalid_email_payload(db: Database) {
 }
 
 #[sqlx::test]
-async fn sign_in_with_invalid_password_payload(db: Database) {
+async fn validate_signup_email_with_already_existing_email(db: Database) {
     let router = create_test_router(db).await;
-    let payload = SigninPayload {
-        password: "",
-        ..Default::default()
-    };
+    get_authenticated_user_cookie(router.clone()).await;
 
     let response = router
         .oneshot(
             Request::builder()
                 .method(Method::POST)
-                .uri("/signin")
+                .uri("/signup/email")
                 .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
-                .body(Body::from(payload.to_form_data()))
+                .body(Body::from(format!("email={}", encode("test@example.com"))))
                 .unwrap(),
         )
         .await
         .unwrap();
 
-    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.status(), StatusCode::CONFLICT);
 }
 
 #[sqlx::test]
-async fn sign_in_with_non_existing_email(db: Database) {
-    let router = create_test_router(db).await;
-    let payload = SigninPayload::default();
+async fn validate_signup_email_is_rate_limited(db: Database) {
+    let router = create_test_router(db)
+        .await
+        .layer(MockConnectInfo(SocketAddr::from(([127, 0, 0, 1], 3000))));
+
+    for i in 0..10 {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/signup/email")
+                    .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                    .body(Body::from(format!(
+                        "email={}",
+                        encode(&format!("test{}@example.com", i))
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 
     let response = router
         .oneshot(
             Request::builder()
                 .method(Method::POST)
-                .uri("/signin")
+                .uri("/signup/email")
                 .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
-                .body(Body::from(payload.to_form_data()))
+                .body(Body::from(format!("email={}", encode("test@example.com"))))
                 .unwrap(),
         )
         .await
         .unwrap();
 
-    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key(RETRY_AFTER));
 }
 
 #[sqlx::test]
-async fn sign_in_with_invalid_password(db: Database) {
+async fn dont_rate_limit_signup_email_without_client_address(db: Database) {
     let router = create_test_router(db).await;
-    let signup_payload = SignupPayload {
-        email: "test@example.com",
-        password: "password123",
-        confirm_password: "password123",
-    };
 
-    let signup_response = router
-        .clone()
+    // Requests without a client address don't share a single limit
+    for i in 0..11 {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/signup/email")
+                    .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                    .body(Body::from(format!(
+                        "email={}",
+                        encode(&format!("test{}@example.com", i))
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+#[sqlx::test]
+async fn validate_signup_password(db: Database) {
+    let router = create_test_router(db).await;
+    let too_long_password = "a".repeat(257);
+    let cases = [
+        (TEST_PASSWORD, StatusCode::OK),
+        ("", StatusCode::UNPROCESSABLE_ENTITY),
+        ("a", StatusCode::UNPROCESSABLE_ENTITY),
+        (too_long_password.as_str(), StatusCode::UNPROCESSABLE_ENTITY),
+        ("password123", StatusCode::UNPROCESSABLE_ENTITY),
+        ("abcabcabc", StatusCode::UNPROCESSABLE_ENTITY),
+    ];
+
+    for (case, status_code) in cases {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/signup/password")
+                    .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                    .body(Body::from(format!("password={}", encode(case))))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), status_code);
+    }
+}
+
+async fn post_signup_password_strength(router: Router, password: &str) -> Response {
+    router
         .oneshot(
             Request::builder()
                 .method(Method::POST)
-                .uri("/signup")
+                .uri("/signup/password-strength")
                 .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
-                .body(Body::from(signup_payload.to_form_data()))
+                .body(Body::from(format!(
+                    "email={}&password={}",
+                    encode("test@example.com"),
+                    encode(password)
+                )))
                 .unwrap(),
         )
         .await
-        .unwrap();
+        .unwrap()
+}
 
-    assert_eq!(signup_response.status(), StatusCode::CREATED);
+#[sqlx::test]
+async fn get_signup_password_strength(db: Database) {
+    let router = create_test_router(db).await;
+    let cases = [
+        ("abcabcabc", "Too weak, a good password is required"),
+        (TEST_PASSWORD, "Strong password"),
+    ];
 
-    let signin_payload = SigninPayload {
-        email: "test@example.com",
-        password: "invalid-password",
-    };
+    for (password, label) in cases {
+        let response = post_signup_password_strength(router.clone(), password).await;
 
-    let signin_response = router
-        .oneshot(
-            Request::builder()
-                .method(Method::POST)
-                .uri("/signin")
-                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
-                .body(Body::from(signin_payload.to_form_data()))
-                .unwrap(),
-        )
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(read_body(response).await.contains(label));
+    }
+}
+
+#[sqlx::test]
+async fn show_configured_min_score_in_signup_password_strength(db: Database) {
+    let router = create_test_router_with_config(db, |config| {
+        config.password.policy.min_score = 4;
+    })
+    .await;
+
+    let response = post_signup_password_strength(router, "abcabcabc").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(read_body(response)
         .await
-        .unwrap();
+        .contains("Too weak, a strong password is required"));
+}
 
-    assert_eq!(signin_response.status(), StatusCode::UNAUTHORIZED);
+#[sqlx::test]
+async fn validate_signup_confirm_password(db: Database) {
+    let router = create_test_router(db).await;
+    let cases = [
+        (TEST_PASSWORD, StatusCode::OK),
+        ("", StatusCode::UNPROCESSABLE_ENTITY),
+        (
+            "mismatched-confirm-password",
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+    ];
+
+    for (case, status_code) in cases {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/signup/confirm-password")
+                    .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                    .body(Body::from(format!(
+                        "password={}&confirm_password={}",
+                        encode(TEST_PASSWORD),
+                        encode(case)
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), status_code);
+    }
 }
 
 #[sqlx::test]
-async fn sign_out(db: Database) {
+async fn get_signin_page(db: Database) {
     let router = create_test_router(db).await;
-    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
 
-    let signout_response = router
-        .clone()
+    let response = router
         .oneshot(
             Request::builder()
-                .method(Method::POST)
-                .uri("/signout")
-                .header(COOKIE, &auth_cookie)
+                .uri("/signin")
                 .body(Body::empty())
                 .unwrap(),
         )
         .await
         .unwrap();
 
-    assert_eq!(signout_response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(is_html_response(&response));
+}
 
-    let protected_response = router
+#[sqlx::test]
+async fn get_signin_page_redirect_on_authenticated(db: Database) {
+    let router = create_test_router(db).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+
+    let response = router
         .oneshot(
             Request::builder()
-                .uri("/protected")
+                .uri("/signin")
                 .header(COOKIE, auth_cookie)
                 .body(Body::empty())
                 .unwrap(),
@@ -504,9 +693,911 @@ async fn sign_out(db: Database) {
         .await
         .unwrap();
 
-    assert_eq!(protected_response.status(), StatusCode::TEMPORARY_REDIRECT);
-    assert_eq!(
-        protected_response.headers().get(LOCATION).unwrap(),
-        "/signin?next=%2Fprotected"
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(response.headers().get(LOCATION).unwrap(), "/protected");
+}
+
+#[sqlx::test]
+async fn sign_in(db: Database) {
+    let router = create_test_router(db).await;
+    let signup_payload = SignupPayload {
+        email: "test@example.com",
+        password: TEST_PASSWORD,
+        confirm_password: TEST_PASSWORD,
+    };
+
+    let signup_response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signup")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(signup_payload.to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(signup_response.status(), StatusCode::CREATED);
+
+    let signin_payload = SigninPayload {
+        email: "test@example.com",
+        password: TEST_PASSWORD,
+    };
+
+    let signin_response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(signin_payload.to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(signin_response.status(), StatusCode::OK);
+
+    let protected_response = router
+        .oneshot(
+            Request::builder()
+                .uri("/protected")
+                .header(COOKIE, signin_response.headers().get(SET_COOKIE).unwrap())
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(protected_response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn sign_in_without_htmx(db: Database) {
+    let router = create_test_router(db).await;
+    get_authenticated_user_cookie(router.clone()).await;
+    let signin_payload = SigninPayload::default();
+
+    let signin_response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(format!(
+                    "{}&next={}",
+                    signin_payload.to_form_data(),
+                    encode("/protected")
+                )))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(signin_response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(
+        signin_response.headers().get(LOCATION).unwrap(),
+        "/protected"
     );
+
+    let protected_response = router
+        .oneshot(
+            Request::builder()
+                .uri("/protected")
+                .header(COOKIE, signin_response.headers().get(SET_COOKIE).unwrap())
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(protected_response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn sign_in_ignores_unsafe_next_url(db: Database) {
+    let router = create_test_router(db).await;
+    get_authenticated_user_cookie(router.clone()).await;
+    let signin_payload = SigninPayload::default();
+    let cases = [
+        "https://example.com",
+        "//example.com",
+        "/\t/example.com",
+        "/\r\nSet-Cookie: id=1",
+    ];
+
+    for next in cases {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/signin")
+                    .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                    .body(Body::from(format!(
+                        "{}&next={}",
+                        signin_payload.to_form_data(),
+                        encode(next)
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers().get(LOCATION).unwrap(), "/protected");
+    }
+}
+
+#[sqlx::test]
+async fn sign_in_with_invalid_credentials_without_htmx_renders_full_page(db: Database) {
+    let router = create_test_router(db).await;
+    let payload = SigninPayload::default();
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(payload.to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(is_html_response(&response));
+    assert!(read_body(response).await.contains("<!DOCTYPE html>"));
+}
+
+#[sqlx::test]
+async fn sign_in_with_invalid_email_payload(db: Database) {
+    let router = create_test_router(db).await;
+    let cases = ["", "invalid-email"];
+
+    for case in cases {
+        let payload = SigninPayload {
+            email: case,
+            ..Default::default()
+        };
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/signin")
+                    .header("HX-Request", "true")
+                    .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                    .body(Body::from(payload.to_form_data()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
+
+#[sqlx::test]
+async fn sign_in_with_invalid_password_payload(db: Database) {
+    let router = create_test_router(db).await;
+    let payload = SigninPayload {
+        password: "",
+        ..Default::default()
+    };
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(payload.to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[sqlx::test]
+async fn sign_in_with_non_existing_email(db: Database) {
+    let router = create_test_router(db).await;
+    let payload = SigninPayload::default();
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(payload.to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test]
+async fn sign_in_with_invalid_password(db: Database) {
+    let router = create_test_router(db).await;
+    let signup_payload = SignupPayload {
+        email: "test@example.com",
+        password: TEST_PASSWORD,
+        confirm_password: TEST_PASSWORD,
+    };
+
+    let signup_response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signup")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(signup_payload.to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(signup_response.status(), StatusCode::CREATED);
+
+    let signin_payload = SigninPayload {
+        email: "test@example.com",
+        password: "invalid-password",
+    };
+
+    let signin_response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(signin_payload.to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(signin_response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[sqlx::test]
+async fn sign_out(db: Database) {
+    let router = create_test_router(db).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+
+    let signout_response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signout")
+                .header("HX-Request", "true")
+                .header(COOKIE, &auth_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(signout_response.status(), StatusCode::NO_CONTENT);
+
+    let protected_response = router
+        .oneshot(
+            Request::builder()
+                .uri("/protected")
+                .header(COOKIE, auth_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(protected_response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(
+        protected_response.headers().get(LOCATION).unwrap(),
+        "/signin?next=%2Fprotected"
+    );
+}
+
+#[sqlx::test]
+async fn sign_out_without_htmx(db: Database) {
+    let router = create_test_router(db).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signout")
+                .header(COOKIE, &auth_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers().get(LOCATION).unwrap(), "/");
+}
+
+#[sqlx::test]
+async fn show_flash_message_after_sign_out(db: Database) {
+    let router = create_test_router(db).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+
+    let signout_response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signout")
+                .header("HX-Request", "true")
+                .header(COOKIE, &auth_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(signout_response.status(), StatusCode::NO_CONTENT);
+    let session_cookie = signout_response.headers().get(SET_COOKIE).unwrap();
+
+    let home_response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/")
+                .header("HX-Request", "true")
+                .header(COOKIE, session_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let body = read_body(home_response).await;
+    assert!(body.contains("You have been signed out"));
+    assert!(body.contains("hx-swap-oob"));
+
+    let next_home_response = router
+        .oneshot(
+            Request::builder()
+                .uri("/")
+                .header(COOKIE, session_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert!(!read_body(next_home_response)
+        .await
+        .contains("You have been signed out"));
+}
+
+async fn create_router_with_secret_keys(db: Database, secret_keys: Vec<Vec<u8>>) -> Router {
+    create_test_router_with_config(db, |config| {
+        config.auth.secret_keys = secret_keys;
+    })
+    .await
+}
+
+#[sqlx::test]
+async fn resign_session_cookie_after_key_rotation(db: Database) {
+    let old_key = vec![1; 64];
+    let new_key = vec![2; 64];
+    let old_router = create_router_with_secret_keys(db.clone(), vec![old_key.clone()]).await;
+    let rotated_router =
+        create_router_with_secret_keys(db.clone(), vec![new_key.clone(), old_key]).await;
+    let new_router = create_router_with_secret_keys(db, vec![new_key]).await;
+    let old_auth_cookie = get_authenticated_user_cookie(old_router).await;
+
+    let response = rotated_router
+        .oneshot(
+            Request::builder()
+                .uri("/protected")
+                .header(COOKIE, old_auth_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let new_auth_cookie = response.headers().get(SET_COOKIE).unwrap().to_owned();
+    let response = new_router
+        .oneshot(
+            Request::builder()
+                .uri("/protected")
+                .header(COOKIE, new_auth_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn use_configured_session_cookie_policy(db: Database) {
+    let router = create_test_router_with_config(db, |config| {
+        config.auth.cookie.name = String::from("__Host-session");
+        config.auth.cookie.secure = true;
+        config.auth.cookie.protection = CookieProtection::Private;
+    })
+    .await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/protected")
+                .header(COOKIE, auth_cookie.clone())
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let auth_cookie = auth_cookie.to_str().unwrap();
+    assert!(auth_cookie.starts_with("__Host-session="));
+    assert!(auth_cookie.contains("Secure"));
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+async fn sign_in_and_get_protected_page(router: Router, form_data: String) -> StatusCode {
+    let signin_response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(form_data))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let auth_cookie = signin_response.headers().get(SET_COOKIE).unwrap();
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/protected")
+                .header(COOKIE, auth_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    response.status()
+}
+
+#[sqlx::test]
+async fn sign_out_session_after_absolute_lifetime(db: Database) {
+    let router = create_test_router_with_config(db, |config| {
+        config.auth.session.absolute_lifetime_minutes = 0;
+    })
+    .await;
+    get_authenticated_user_cookie(router.clone()).await;
+    let signin_payload = SigninPayload::default();
+
+    let status_code =
+        sign_in_and_get_protected_page(router.clone(), signin_payload.to_form_data()).await;
+    let remember_me_status_code = sign_in_and_get_protected_page(
+        router,
+        format!("{}&remember_me=true", signin_payload.to_form_data()),
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(remember_me_status_code, StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn get_reauthenticate_page(db: Database) {
+    let router = create_test_router(db).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/reauthenticate?next=%2Fprotected")
+                .header(COOKIE, auth_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(read_body(response).await.contains(r#"name="next""#));
+}
+
+#[sqlx::test]
+async fn reauthenticate(db: Database) {
+    let router = create_test_router(db).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+    let cases = [
+        ("/protected?tab=1", "/protected?tab=1"),
+        ("https://example.com", "/protected"),
+    ];
+
+    for (next, expected_location) in cases {
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/reauthenticate")
+                    .header("HX-Request", "true")
+                    .header(COOKIE, auth_cookie.clone())
+                    .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                    .body(Body::from(format!(
+                        "password={}&next={}",
+                        encode(TEST_PASSWORD),
+                        encode(next)
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let location = response.headers().get("HX-Location").unwrap();
+        assert!(location
+            .to_str()
+            .unwrap()
+            .contains(&format!(r#""path":"{}""#, expected_location)));
+    }
+}
+
+#[sqlx::test]
+async fn reauthenticate_with_invalid_password(db: Database) {
+    let router = create_test_router(db).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/reauthenticate")
+                .header("HX-Request", "true")
+                .header(COOKIE, auth_cookie)
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(format!("password={}", encode("invalid"))))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(read_body(response).await.contains("Incorrect password"));
+}
+
+#[sqlx::test]
+async fn rate_limit_reauthentication(db: Database) {
+    let router = create_test_router(db).await;
+    let auth_cookie = get_authenticated_user_cookie(router.clone()).await;
+    let create_request = |password: &str| {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/reauthenticate")
+            .header("HX-Request", "true")
+            .header(COOKIE, &auth_cookie)
+            .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+            .body(Body::from(format!("password={}", encode(password))))
+            .unwrap()
+    };
+
+    for _ in 0..5 {
+        let response = router
+            .clone()
+            .oneshot(create_request("invalid"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    let response = router.oneshot(create_request(TEST_PASSWORD)).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key(RETRY_AFTER));
+}
+
+#[sqlx::test]
+async fn rehash_outdated_password_on_sign_in(db: Database) {
+    let outdated_router = create_test_router_with_config(db.clone(), |config| {
+        config.password.hashing =
+            PasswordHashing::new(Algorithm::Argon2i, Params::new(1024, 1, 1, None).unwrap());
+    })
+    .await;
+    get_authenticated_user_cookie(outdated_router).await;
+    let router = create_test_router(db.clone()).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(SigninPayload::default().to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let password: String = sqlx::query_scalar("SELECT password FROM users WHERE email = $1")
+        .bind("test@example.com")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert!(!Config::from_env().password.hashing.needs_rehash(&password));
+}
+
+#[sqlx::test]
+async fn keep_other_sessions_after_rehash(db: Database) {
+    let outdated_router = create_test_router_with_config(db.clone(), |config| {
+        config.password.hashing =
+            PasswordHashing::new(Algorithm::Argon2i, Params::new(1024, 1, 1, None).unwrap());
+    })
+    .await;
+    let other_session_cookie = get_authenticated_user_cookie(outdated_router).await;
+    let router = create_test_router(db).await;
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(SigninPayload::default().to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/protected")
+                .header(COOKIE, other_session_cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn sign_in_with_password_hashed_with_removed_pepper(db: Database) {
+    let peppered_router = create_test_router_with_config(db.clone(), |config| {
+        config.password.hashing = config
+            .password
+            .hashing
+            .clone()
+            .with_peppers(vec![Pepper::new("1", vec![1; 32]).unwrap()])
+            .unwrap();
+    })
+    .await;
+    get_authenticated_user_cookie(peppered_router).await;
+    let router = create_test_router(db).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(SigninPayload::default().to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(read_body(response)
+        .await
+        .contains("Incorrect email or password"));
+}
+
+#[sqlx::test]
+async fn sign_in_imported_user_with_legacy_hash(db: Database) {
+    let users = vec![ImportedUser {
+        email: String::from("test@example.com"),
+        password: bcrypt::hash(TEST_PASSWORD, 4).unwrap(),
+    }];
+    let summary = import_users(users, &db).await;
+    assert_eq!(summary.imported, 1);
+    let router = create_test_router(db.clone()).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(SigninPayload::default().to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let password: String = sqlx::query_scalar("SELECT password FROM users WHERE email = $1")
+        .bind("test@example.com")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert!(password.starts_with("$argon2id$"));
+}
+
+#[sqlx::test]
+async fn sign_up_with_breached_password(db: Database) {
+    let password = TEST_PASSWORD;
+    let hash = format!("{:X}", Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = hash.split_at(5);
+    let directory = tempdir().unwrap();
+    write(
+        directory.path().join(format!("{}.txt", prefix)),
+        format!("{}:42\r\n", suffix),
+    )
+    .unwrap();
+    let router = create_test_router_with_config(db, |config| {
+        config.password.breached_ranges_directory =
+            Some(directory.path().to_string_lossy().into_owned());
+    })
+    .await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signup")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(SignupPayload::default().to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(read_body(response)
+        .await
+        .contains("This password appeared in a data breach"));
+}
+
+#[sqlx::test]
+async fn reject_password_when_breach_check_fails(db: Database) {
+    // The range of the password is missing, as in an incomplete dataset
+    let directory = tempdir().unwrap();
+    let router = create_test_router_with_config(db, |config| {
+        config.password.breached_ranges_directory =
+            Some(directory.path().to_string_lossy().into_owned());
+    })
+    .await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signup")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(SignupPayload::default().to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(read_body(response)
+        .await
+        .contains("be checked against data breaches"));
+}
+
+#[sqlx::test]
+async fn accept_password_when_breach_check_fails_open(db: Database) {
+    let directory = tempdir().unwrap();
+    let router = create_test_router_with_config(db, |config| {
+        config.password.breached_ranges_directory =
+            Some(directory.path().to_string_lossy().into_owned());
+        config.password.breached_check_fail_open = true;
+    })
+    .await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signup")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(SignupPayload::default().to_form_data()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+#[sqlx::test]
+async fn get_503_page_when_password_hashing_is_saturated(db: Database) {
+    let router = create_test_router_with_config(db, |config| {
+        config.password.hashing = config.password.hashing.clone().with_limits(1, 0);
+    })
+    .await;
+    let emails = [
+        "first@example.com",
+        "second@example.com",
+        "third@example.com",
+    ];
+
+    // Only one hash runs at once and none can wait, so the requests arriving
+    // during the first hash are rejected
+    let requests = emails.map(|email| {
+        let router = router.clone();
+        spawn(async move {
+            router
+                .oneshot(
+                    Request::builder()
+                        .method(Method::POST)
+                        .uri("/signup")
+                        .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                        .body(Body::from(
+                            SignupPayload {
+                                email,
+                                ..Default::default()
+                            }
+                            .to_form_data(),
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+        })
+    });
+    let mut rejected = None;
+    for request in requests {
+        let response = request.await.unwrap();
+        if response.status() == StatusCode::SERVICE_UNAVAILABLE {
+            rejected = Some(response);
+        }
+    }
+
+    let response = rejected.expect("No request was rejected");
+    assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "1");
+    assert!(is_html_response(&response));
+    let body = read_body(response).await;
+    assert!(body.contains("<!DOCTYPE html>"));
+    assert!(body.contains("The server is busy, please try again in a moment."));
 }
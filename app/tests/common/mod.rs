@@ -1,10 +1,12 @@
 use app::{
-    config::Config,
+    config::{AdminConfig, Config},
     db::connection::{setup_session_store, Database},
-    server::create_router,
+    libs::health::Readiness,
+    server::{create_admin_server_router, create_router},
+    tracing::LogFilterHandle,
 };
 use axum::{
-    body::Body,
+    body::{to_bytes, Body},
     extract::Request,
     http::{
         header::{CONTENT_TYPE, SET_COOKIE},
@@ -15,14 +17,36 @@ use axum::{
 };
 use mime::{APPLICATION_WWW_FORM_URLENCODED, TEXT_HTML_UTF_8};
 use tower::ServiceExt;
+use tracing_subscriber::{reload::Layer as ReloadLayer, EnvFilter};
 use urlencoding::encode;
 
+// Strong enough for the default password policy
+pub const TEST_PASSWORD: &str = "kV9#mRq2!zXp";
+pub const TEST_ADMIN_TOKEN: &str = "test-admin-token-0123456789abcdef";
+
 pub async fn create_test_router(db: Database) -> Router {
+    create_test_router_with_config(db, |_| {}).await
+}
+
+pub async fn create_test_router_with_config(
+    db: Database,
+    configure: impl FnOnce(&mut Config),
+) -> Router {
     // TODO: Improve performance by cloning database (available for Postgres) instead
     // of recreating db with all migrations for each test.
-    let config = Config::from_env();
+    let mut config = Config::from_env();
+    configure(&mut config);
     let session_store = setup_session_store(db.clone()).await;
-    create_router(&config, db, session_store)
+    create_router(&config, db, session_store, Readiness::default())
+}
+
+pub fn create_test_admin_router(db: Database) -> Router {
+    let (_layer, handle) = ReloadLayer::new(EnvFilter::new("info"));
+    let config = AdminConfig {
+        address: None,
+        token: Some(TEST_ADMIN_TOKEN.to_owned()),
+    };
+    create_admin_server_router(&config, db, LogFilterHandle::new(handle))
 }
 
 pub fn is_html_response(response: &Response) -> bool {
@@ -34,12 +58,40 @@ pub fn is_html_response(response: &Response) -> bool {
         })
 }
 
+pub async fn read_body(response: Response) -> String {
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+// Signs in the user created by `get_authenticated_user_cookie`, the cookie of
+// the new session is in the response
+pub async fn sign_in(router: Router) -> Response {
+    let form_data = format!(
+        "email={}&password={}",
+        encode("test@example.com"),
+        encode(TEST_PASSWORD)
+    );
+
+    router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signin")
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(form_data))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
 pub async fn get_authenticated_user_cookie(router: Router) -> HeaderValue {
     let form_data = format!(
         "email={}&password={}&confirm_password={}",
         encode("test@example.com"),
-        encode("password123"),
-        encode("password123")
+        encode(TEST_PASSWORD),
+        encode(TEST_PASSWORD)
     );
 
     let response = router
@@ -47,6 +99,7 @@ pub async fn get_authenticated_user_cookie(router: Router) -> HeaderValue {
             Request::builder()
                 .method(Method::POST)
                 .uri("/signup")
+                .header("HX-Request", "true")
                 .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
                 .body(Body::from(form_data))
                 .unwrap(),
@@ -0,0 +1,85 @@
+use app::{
+    config::Config,
+    db::connection::{setup_session_store, Database},
+    libs::health::Readiness,
+    server::create_router,
+};
+use axum::{body::Body, extract::Request, http::StatusCode};
+use tower::ServiceExt;
+
+pub mod common;
+use common::{create_test_router, read_body};
+
+#[sqlx::test]
+async fn get_health(db: Database) {
+    let router = create_test_router(db).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/healthz")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[sqlx::test]
+async fn get_readiness(db: Database) {
+    let router = create_test_router(db).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/readyz")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = read_body(response).await;
+    assert!(body.contains(r#""status":"ready""#));
+    assert!(body.contains(r#""database":"ok""#));
+    assert!(body.contains(r#""migrations":"ok""#));
+    assert!(body.contains(r#""session_store":"ok""#));
+}
+
+#[sqlx::test]
+async fn get_readiness_during_shutdown(db: Database) {
+    let config = Config::from_env();
+    let session_store = setup_session_store(db.clone()).await;
+    let readiness = Readiness::default();
+    let router = create_router(&config, db, session_store, readiness.clone());
+    readiness.set_shutting_down();
+
+    let health_response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/healthz")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let readiness_response = router
+        .oneshot(
+            Request::builder()
+                .uri("/readyz")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(health_response.status(), StatusCode::OK);
+    assert_eq!(readiness_response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(read_body(readiness_response)
+        .await
+        .contains(r#""status":"shutting_down""#));
+}
@@ -0,0 +1,142 @@
+use app::{config::LogFormat, db::connection::Database, tracing::create_log_layer};
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header::CONTENT_TYPE, Method},
+    Router,
+};
+use mime::APPLICATION_WWW_FORM_URLENCODED;
+use std::{
+    io::{Result as IoResult, Write},
+    sync::{Arc, Mutex},
+};
+use tower::ServiceExt;
+use tracing::{info, subscriber::set_default};
+use tracing_subscriber::{
+    fmt::{writer::BoxMakeWriter, MakeWriter},
+    layer::SubscriberExt,
+    EnvFilter,
+};
+use urlencoding::encode;
+
+pub mod common;
+use common::{create_test_router, TEST_PASSWORD};
+
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl LogBuffer {
+    fn read(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogBuffer {
+    type Writer = LogBuffer;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+async fn post_form(router: Router, uri: &str, form_data: String) {
+    router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header("HX-Request", "true")
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(form_data))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+}
+
+async fn assert_secrets_are_not_logged(db: Database, format: LogFormat) {
+    let logs = LogBuffer::default();
+    let subscriber = tracing_subscriber::registry()
+        .with(EnvFilter::new("trace"))
+        .with(create_log_layer(format, BoxMakeWriter::new(logs.clone())));
+    let _guard = set_default(subscriber);
+    let router = create_test_router(db).await;
+
+    post_form(
+        router.clone(),
+        "/signup",
+        format!(
+            "email={}&password={}&confirm_password={}",
+            encode("test@example.com"),
+            encode(TEST_PASSWORD),
+            encode(TEST_PASSWORD)
+        ),
+    )
+    .await;
+    post_form(
+        router.clone(),
+        "/signin",
+        format!(
+            "email={}&password={}",
+            encode("test@example.com"),
+            encode("wrong-password")
+        ),
+    )
+    .await;
+    post_form(
+        router,
+        "/signin",
+        format!(
+            "email={}&password={}",
+            encode("unknown@example.com"),
+            encode(TEST_PASSWORD)
+        ),
+    )
+    .await;
+    info!(
+        cookie = "id=session-id; theme=dark",
+        "Received token=\"token-value\" and password=password-value"
+    );
+
+    let logs = logs.read();
+    assert!(logs.contains("Finished processing request"));
+    for secret in [
+        "test@example.com",
+        "unknown@example.com",
+        TEST_PASSWORD,
+        "wrong-password",
+        "$argon2",
+        "session-id",
+        "token-value",
+        "password-value",
+    ] {
+        assert!(!logs.contains(secret), "{} leaked into the logs", secret);
+    }
+    if let LogFormat::Json = format {
+        for line in logs.lines() {
+            if let Err(e) = serde_json::from_str::<serde_json::Value>(line) {
+                panic!("Invalid JSON log line {}: {}", line, e);
+            }
+        }
+    }
+}
+
+#[sqlx::test]
+async fn secrets_are_not_logged(db: Database) {
+    assert_secrets_are_not_logged(db, LogFormat::Compact).await;
+}
+
+#[sqlx::test]
+async fn secrets_are_not_logged_in_json(db: Database) {
+    assert_secrets_are_not_logged(db, LogFormat::Json).await;
+}
@@ -10,7 +10,10 @@ use axum::{
 use tower::ServiceExt;
 
 pub mod common;
-use common::{create_test_router, get_authenticated_user_cookie, is_html_response};
+use common::{
+    create_test_admin_router, create_test_router, create_test_router_with_config,
+    get_authenticated_user_cookie, is_html_response, read_body,
+};
 
 #[sqlx::test]
 async fn get_home_page(db: Database) {
@@ -75,3 +78,183 @@ async fn dont_cache_pages_by_default(db: Database) {
     assert_eq!(response.status(), StatusCode::OK);
     assert_eq!(response.headers().get(CACHE_CONTROL).unwrap(), "no-cache");
 }
+
+#[sqlx::test]
+async fn get_page_fragment_for_htmx_request(db: Database) {
+    let router = create_test_router(db).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/")
+                .header("HX-Request", "true")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!read_body(response).await.contains("<!DOCTYPE html>"));
+}
+
+#[sqlx::test]
+async fn get_full_page_for_htmx_history_restore_request(db: Database) {
+    let router = create_test_router(db).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/")
+                .header("HX-Request", "true")
+                .header("HX-History-Restore-Request", "true")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(read_body(response).await.contains("<!DOCTYPE html>"));
+}
+
+#[sqlx::test]
+async fn get_full_page_for_boosted_request(db: Database) {
+    let router = create_test_router(db).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/")
+                .header("HX-Request", "true")
+                .header("HX-Boosted", "true")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(read_body(response).await.contains("<!DOCTYPE html>"));
+}
+
+#[sqlx::test]
+async fn generate_request_id(db: Database) {
+    let router = create_test_router(db).await;
+
+    let response = router
+        .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().get("X-Request-Id").unwrap().is_empty());
+}
+
+#[sqlx::test]
+async fn propagate_incoming_request_id(db: Database) {
+    let router = create_test_router(db).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/")
+                .header("X-Request-Id", "incoming-request-id")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get("X-Request-Id").unwrap(),
+        "incoming-request-id"
+    );
+}
+
+#[sqlx::test]
+async fn replace_invalid_incoming_request_id(db: Database) {
+    let router = create_test_router(db).await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/")
+                .header("X-Request-Id", "invalid request id")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let request_id = response.headers().get("X-Request-Id").unwrap();
+    assert!(!request_id.is_empty());
+    assert_ne!(request_id, "invalid request id");
+}
+
+#[sqlx::test]
+async fn get_metrics(db: Database) {
+    let router = create_test_router(db.clone()).await;
+    router
+        .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let response = create_test_admin_router(db)
+        .oneshot(
+            Request::builder()
+                .uri("/metrics")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = read_body(response).await;
+    assert!(body.contains(r#"http_requests_total{method="GET",route="/",status="200"}"#));
+    assert!(body.contains("db_pool_connections"));
+}
+
+#[sqlx::test]
+async fn serve_metrics_on_public_router_without_admin_address(db: Database) {
+    let router = create_test_router_with_config(db, |config| {
+        config.admin.address = None;
+    })
+    .await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/metrics")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(read_body(response).await.contains("db_pool_connections"));
+}
+
+#[sqlx::test]
+async fn dont_serve_metrics_on_public_router_with_admin_address(db: Database) {
+    let router = create_test_router_with_config(db, |config| {
+        config.admin.address = Some("127.0.0.1:3001".parse().unwrap());
+    })
+    .await;
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .uri("/metrics")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
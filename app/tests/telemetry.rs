@@ -0,0 +1,97 @@
+use app::db::connection::Database;
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header::CONTENT_TYPE, Method, StatusCode},
+};
+use mime::APPLICATION_WWW_FORM_URLENCODED;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::{
+    export::trace::SpanData, testing::trace::InMemorySpanExporter, trace::TracerProvider,
+};
+use tower::ServiceExt;
+use tracing::subscriber::set_default;
+use tracing_subscriber::{layer::SubscriberExt, registry};
+use urlencoding::encode;
+
+pub mod common;
+use common::{create_test_router, TEST_PASSWORD};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+fn find_span<'a>(spans: &'a [SpanData], name: &str) -> &'a SpanData {
+    spans
+        .iter()
+        .find(|span| span.name == name)
+        .unwrap_or_else(|| panic!("Span {} not exported", name))
+}
+
+#[sqlx::test]
+async fn export_request_trace(db: Database) {
+    let exporter = InMemorySpanExporter::default();
+    let tracer_provider = TracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber =
+        registry().with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+    let _guard = set_default(subscriber);
+    let router = create_test_router(db).await;
+    let form_data = format!(
+        "email={}&password={}&confirm_password={}",
+        encode("test@example.com"),
+        encode(TEST_PASSWORD),
+        encode(TEST_PASSWORD)
+    );
+
+    let response = router
+        .oneshot(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/signup")
+                .header("HX-Request", "true")
+                .header(
+                    "traceparent",
+                    format!("00-{}-{}-01", TRACE_ID, PARENT_SPAN_ID),
+                )
+                .header(CONTENT_TYPE, APPLICATION_WWW_FORM_URLENCODED.as_ref())
+                .body(Body::from(form_data))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let spans = exporter.get_finished_spans().unwrap();
+    let request_span = find_span(&spans, "request");
+    let handler_span = find_span(&spans, "post_signup");
+    let controller_span = find_span(&spans, "sign_up");
+    let hash_span = find_span(&spans, "hash_password");
+    let query_span = find_span(&spans, "create_user");
+    for span in [
+        request_span,
+        handler_span,
+        controller_span,
+        hash_span,
+        query_span,
+    ] {
+        assert_eq!(span.span_context.trace_id().to_string(), TRACE_ID);
+    }
+    assert_eq!(request_span.parent_span_id.to_string(), PARENT_SPAN_ID);
+    assert_eq!(
+        handler_span.parent_span_id,
+        request_span.span_context.span_id()
+    );
+    assert_eq!(
+        controller_span.parent_span_id,
+        handler_span.span_context.span_id()
+    );
+    assert_eq!(
+        hash_span.parent_span_id,
+        controller_span.span_context.span_id()
+    );
+    assert_eq!(
+        query_span.parent_span_id,
+        controller_span.span_context.span_id()
+    );
+}